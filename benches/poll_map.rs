@@ -0,0 +1,70 @@
+//! Compares the boxed `poll::map_async`/`filter_async` against the unboxed
+//! `poll::map`, which takes a closure returning a nameable future.
+//!
+//! Run with `cargo bench`.
+
+#![feature(test, async_iterator, async_for_loop, gen_blocks)]
+
+extern crate test;
+
+use std::async_iter::AsyncIterator;
+
+use async_iteration_scratch::{
+    block_on,
+    poll::{filter_async, map::map, map_async, AsyncIteratorExt},
+};
+use test::{black_box, Bencher};
+
+const ITEMS: u64 = 1000;
+
+fn source() -> impl AsyncIterator<Item = u64> {
+    async gen {
+        for i in 0..ITEMS {
+            yield black_box(i);
+        }
+    }
+}
+
+/// The predicate for both filter benchmarks.
+fn is_multiple_of_3(x: &u64) -> bool {
+    x.is_multiple_of(3)
+}
+
+async fn sum(iter: impl AsyncIterator<Item = u64>) -> u64 {
+    let mut total = 0;
+    for await x in iter {
+        total += x;
+    }
+    total
+}
+
+#[bench]
+fn map_unboxed(b: &mut Bencher) {
+    b.iter(|| block_on(sum(map(source(), |x| async move { x * 2 }))));
+}
+
+#[bench]
+fn map_boxed(b: &mut Bencher) {
+    b.iter(|| block_on(sum(map_async(source(), async |x| x * 2))));
+}
+
+#[bench]
+fn filter_unboxed(b: &mut Bencher) {
+    // The unboxed closure can't borrow the item, so it hands it back when it
+    // passes and `filter_map` drops the rest.
+    b.iter(|| {
+        block_on(sum(map(source(), |x| async move {
+            is_multiple_of_3(&x).then_some(x)
+        })
+        .filter_map(|x| x)))
+    });
+}
+
+#[bench]
+fn filter_boxed(b: &mut Bencher) {
+    b.iter(|| {
+        block_on(sum(filter_async(source(), async |x: &u64| {
+            is_multiple_of_3(x)
+        })))
+    });
+}
@@ -1,8 +1,9 @@
 use std::pin::Pin;
 
-mod map;
+pub mod map;
 // Merge is impossible currently.
 // mod merge;
+pub mod timeout;
 
 pub trait AsyncIterator {
     type Item;
@@ -25,7 +26,7 @@ pub trait PinnedAsyncIterator {
     async fn next(self: Pin<&mut Self>) -> Option<Self::Item>;
 }
 
-fn async_iter_from_iter<I: Iterator>(iter: I) -> impl AsyncIterator<Item = I::Item> {
+pub fn async_iter_from_iter<I: Iterator>(iter: I) -> impl AsyncIterator<Item = I::Item> {
     struct Iter<I: Iterator>(I);
 
     impl<I: Iterator> AsyncIterator for Iter<I> {
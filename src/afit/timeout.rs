@@ -0,0 +1,163 @@
+//! Time limits for `afit` async iterators.
+//!
+//! These wrap each call to `next` in a [`timeout`], so a call that runs out of
+//! time is dropped part way through. Whether that loses anything depends on
+//! the iterator.
+
+use std::time::Duration;
+
+use super::AsyncIterator;
+use crate::{
+    future_combinators::{deadline, timeout, Elapsed},
+    time::{Clock, Instant},
+};
+
+/// Limits how long each call to `next` can take.
+///
+/// A call that takes longer than `duration` is cancelled and yields
+/// `Err(Elapsed)`. The iterator can still be called again afterwards.
+pub fn timeout_each<I: AsyncIterator>(
+    clock: &Clock,
+    duration: Duration,
+    iter: I,
+) -> TimeoutEach<I> {
+    TimeoutEach {
+        iter,
+        clock: clock.clone(),
+        duration,
+    }
+}
+
+pub struct TimeoutEach<I> {
+    iter: I,
+    clock: Clock,
+    duration: Duration,
+}
+
+impl<I: AsyncIterator> AsyncIterator for TimeoutEach<I> {
+    type Item = Result<I::Item, Elapsed>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        match timeout(&self.clock, self.duration, self.iter.next()).await {
+            Ok(item) => item.map(Ok),
+            Err(elapsed) => Some(Err(elapsed)),
+        }
+    }
+}
+
+/// Limits how long the whole iterator can take.
+///
+/// Once `duration` has passed, the call to `next` in progress is cancelled and
+/// yields `Err(Elapsed)`, and every call after that returns `None`.
+pub fn timeout_total<I: AsyncIterator>(
+    clock: &Clock,
+    duration: Duration,
+    iter: I,
+) -> TimeoutTotal<I> {
+    TimeoutTotal {
+        iter,
+        clock: clock.clone(),
+        deadline: clock.now() + duration,
+        done: false,
+    }
+}
+
+pub struct TimeoutTotal<I> {
+    iter: I,
+    clock: Clock,
+    deadline: Instant,
+    done: bool,
+}
+
+impl<I: AsyncIterator> AsyncIterator for TimeoutTotal<I> {
+    type Item = Result<I::Item, Elapsed>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match deadline(&self.clock, self.deadline, self.iter.next()).await {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(elapsed) => {
+                self.done = true;
+                Some(Err(elapsed))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{timeout_each, timeout_total};
+    use crate::{afit::AsyncIterator, future_combinators::Elapsed, time::Clock};
+
+    /// Yields 1, 2 and 3, sleeping for that many seconds before each.
+    struct Sleepy<'a> {
+        clock: &'a Clock,
+        next: u64,
+    }
+
+    impl AsyncIterator for Sleepy<'_> {
+        type Item = u64;
+
+        async fn next(&mut self) -> Option<u64> {
+            if self.next > 3 {
+                return None;
+            }
+            self.clock.sleep(Duration::from_secs(self.next)).await;
+            self.next += 1;
+            Some(self.next - 1)
+        }
+    }
+
+    #[test]
+    fn each_call() {
+        let clock = Clock::new();
+        let sleepy = Sleepy {
+            clock: &clock,
+            next: 1,
+        };
+        let mut iter = timeout_each(&clock, Duration::from_millis(2500), sleepy);
+        let items = clock.block_on(async {
+            let mut items = vec![];
+            for _ in 0..4 {
+                items.push(iter.next().await);
+            }
+            items
+        });
+        // The third call starts its sleep over each time it is cancelled.
+        assert_eq!(
+            items,
+            vec![
+                Some(Ok(1)),
+                Some(Ok(2)),
+                Some(Err(Elapsed)),
+                Some(Err(Elapsed))
+            ]
+        );
+    }
+
+    #[test]
+    fn whole_iterator() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let sleepy = Sleepy {
+            clock: &clock,
+            next: 1,
+        };
+        let mut items = vec![];
+        clock.block_on(
+            timeout_total(&clock, Duration::from_secs(4), sleepy).for_each(async |item| {
+                items.push(item);
+            }),
+        );
+        assert_eq!(items, vec![Ok(1), Ok(2), Err(Elapsed)]);
+        assert_eq!(clock.now() - start, Duration::from_secs(4));
+    }
+}
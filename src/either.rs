@@ -0,0 +1,415 @@
+//! A value of one of two types.
+//!
+//! This is what `merge` and `race` return to say which side an item came from.
+//! [`EitherOrBoth`] is the same idea for `zip_longest`, where both sides may
+//! be present at once.
+
+use std::{
+    async_iter::AsyncIterator,
+    future::Future,
+    ops::ControlFlow,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{afit, push};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+use Either::{Left, Right};
+
+impl<A, B> Either<A, B> {
+    pub fn is_left(&self) -> bool {
+        matches!(self, Left(_))
+    }
+
+    pub fn is_right(&self) -> bool {
+        matches!(self, Right(_))
+    }
+
+    /// Returns the left value, or `None` if this is `Right`.
+    pub fn left(self) -> Option<A> {
+        match self {
+            Left(a) => Some(a),
+            Right(_) => None,
+        }
+    }
+
+    /// Returns the right value, or `None` if this is `Left`.
+    pub fn right(self) -> Option<B> {
+        match self {
+            Left(_) => None,
+            Right(b) => Some(b),
+        }
+    }
+
+    pub fn map_left<C>(self, f: impl FnOnce(A) -> C) -> Either<C, B> {
+        match self {
+            Left(a) => Left(f(a)),
+            Right(b) => Right(b),
+        }
+    }
+
+    pub fn map_right<C>(self, f: impl FnOnce(B) -> C) -> Either<A, C> {
+        match self {
+            Left(a) => Left(a),
+            Right(b) => Right(f(b)),
+        }
+    }
+
+    /// Applies `f` to a left value or `g` to a right value, keeping the side.
+    pub fn map_either<C, D>(self, f: impl FnOnce(A) -> C, g: impl FnOnce(B) -> D) -> Either<C, D> {
+        match self {
+            Left(a) => Left(f(a)),
+            Right(b) => Right(g(b)),
+        }
+    }
+
+    /// Collapses both sides into one value by applying `f` or `g`.
+    pub fn either<R>(self, f: impl FnOnce(A) -> R, g: impl FnOnce(B) -> R) -> R {
+        match self {
+            Left(a) => f(a),
+            Right(b) => g(b),
+        }
+    }
+
+    /// Swaps `Left` and `Right`.
+    pub fn flip(self) -> Either<B, A> {
+        match self {
+            Left(a) => Right(a),
+            Right(b) => Left(b),
+        }
+    }
+
+    pub fn as_ref(&self) -> Either<&A, &B> {
+        match self {
+            Left(a) => Left(a),
+            Right(b) => Right(b),
+        }
+    }
+
+    pub fn as_mut(&mut self) -> Either<&mut A, &mut B> {
+        match self {
+            Left(a) => Left(a),
+            Right(b) => Right(b),
+        }
+    }
+
+    /// Projects a pinned `Either` to whichever side it holds.
+    pub fn as_pin_mut(self: Pin<&mut Self>) -> Either<Pin<&mut A>, Pin<&mut B>> {
+        // SAFETY: we never move out of `self`, and the projected references
+        // stay pinned.
+        unsafe {
+            match self.get_unchecked_mut() {
+                Left(a) => Left(Pin::new_unchecked(a)),
+                Right(b) => Right(Pin::new_unchecked(b)),
+            }
+        }
+    }
+}
+
+impl<T> Either<T, T> {
+    /// Returns the value from whichever side is present.
+    pub fn into_inner(self) -> T {
+        match self {
+            Left(t) | Right(t) => t,
+        }
+    }
+}
+
+/// A value from one side or the other, or from both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EitherOrBoth<A, B> {
+    Both(A, B),
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> EitherOrBoth<A, B> {
+    pub fn is_both(&self) -> bool {
+        matches!(self, EitherOrBoth::Both(..))
+    }
+
+    /// Returns the left value, if there is one.
+    pub fn left(self) -> Option<A> {
+        match self {
+            EitherOrBoth::Both(a, _) | EitherOrBoth::Left(a) => Some(a),
+            EitherOrBoth::Right(_) => None,
+        }
+    }
+
+    /// Returns the right value, if there is one.
+    pub fn right(self) -> Option<B> {
+        match self {
+            EitherOrBoth::Both(_, b) | EitherOrBoth::Right(b) => Some(b),
+            EitherOrBoth::Left(_) => None,
+        }
+    }
+
+    /// Returns both values, or `None` if only one side is present.
+    pub fn both(self) -> Option<(A, B)> {
+        match self {
+            EitherOrBoth::Both(a, b) => Some((a, b)),
+            _ => None,
+        }
+    }
+
+    /// Splits into the two sides, either of which may be missing.
+    pub fn into_options(self) -> (Option<A>, Option<B>) {
+        match self {
+            EitherOrBoth::Both(a, b) => (Some(a), Some(b)),
+            EitherOrBoth::Left(a) => (Some(a), None),
+            EitherOrBoth::Right(b) => (None, Some(b)),
+        }
+    }
+}
+
+// The trait impls below let a function pick one of two pipelines at runtime and
+// return it without boxing.
+
+impl<A, B> Future for Either<A, B>
+where
+    A: Future,
+    B: Future<Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.as_pin_mut() {
+            Left(a) => a.poll(cx),
+            Right(b) => b.poll(cx),
+        }
+    }
+}
+
+impl<A, B> AsyncIterator for Either<A, B>
+where
+    A: AsyncIterator,
+    B: AsyncIterator<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.as_pin_mut() {
+            Left(a) => a.poll_next(cx),
+            Right(b) => b.poll_next(cx),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Left(a) => a.size_hint(),
+            Right(b) => b.size_hint(),
+        }
+    }
+}
+
+impl<A, B> afit::AsyncIterator for Either<A, B>
+where
+    A: afit::AsyncIterator,
+    B: afit::AsyncIterator<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Left(a) => a.next().await,
+            Right(b) => b.next().await,
+        }
+    }
+}
+
+impl<A, B> push::Stream for Either<A, B>
+where
+    A: push::Stream,
+    B: push::Stream<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    async fn exec(self, f: impl async FnMut(Option<Self::Item>) -> ControlFlow<()>) {
+        match self {
+            Left(a) => a.exec(f).await,
+            Right(b) => b.exec(f).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{async_iter::AsyncIterator, collections::HashSet, pin::pin};
+
+    use super::{
+        Either::{self, Left, Right},
+        EitherOrBoth,
+    };
+    use crate::{afit, block_on, poll, push};
+
+    #[test]
+    fn accessors() {
+        let l: Either<i32, &str> = Left(1);
+        let r: Either<i32, &str> = Right("one");
+
+        assert!(l.is_left() && !l.is_right());
+        assert!(r.is_right() && !r.is_left());
+        assert_eq!(l.left(), Some(1));
+        assert_eq!(l.right(), None);
+        assert_eq!(r.left(), None);
+        assert_eq!(r.right(), Some("one"));
+    }
+
+    #[test]
+    fn either_or_both() {
+        let both: EitherOrBoth<i32, &str> = EitherOrBoth::Both(1, "one");
+        let left: EitherOrBoth<i32, &str> = EitherOrBoth::Left(2);
+        let right: EitherOrBoth<i32, &str> = EitherOrBoth::Right("three");
+
+        assert!(both.is_both() && !left.is_both());
+        assert_eq!(both.left(), Some(1));
+        assert_eq!(right.left(), None);
+        assert_eq!(both.right(), Some("one"));
+        assert_eq!(left.right(), None);
+        assert_eq!(both.both(), Some((1, "one")));
+        assert_eq!(right.both(), None);
+        assert_eq!(left.into_options(), (Some(2), None));
+        assert_eq!(right.into_options(), (None, Some("three")));
+    }
+
+    #[test]
+    fn maps() {
+        let l: Either<i32, &str> = Left(1);
+        let r: Either<i32, &str> = Right("one");
+
+        assert_eq!(l.map_left(|x| x + 1), Left(2));
+        assert_eq!(r.map_left(|x| x + 1), Right("one"));
+        assert_eq!(r.map_right(str::len), Right(3));
+        assert_eq!(l.map_either(|x| x * 10, str::len), Left(10));
+        assert_eq!(r.either(|x| x as usize, str::len), 3);
+        assert_eq!(l.flip(), Right(1));
+    }
+
+    #[test]
+    fn into_inner() {
+        let items: Vec<Either<i32, i32>> = vec![Left(1), Right(2)];
+        let values: Vec<i32> = items.into_iter().map(Either::into_inner).collect();
+        assert_eq!(values, vec![1, 2]);
+    }
+
+    #[test]
+    fn references() {
+        let mut e: Either<i32, String> = Right("a".to_string());
+        assert_eq!(e.as_ref().map_right(String::as_str), Right("a"));
+        if let Right(s) = e.as_mut() {
+            s.push('b');
+        }
+        assert_eq!(e, Right("ab".to_string()));
+
+        let mut pinned = pin!(Left::<i32, String>(5));
+        if let Left(mut x) = pinned.as_mut().as_pin_mut() {
+            *x += 1;
+        }
+        assert_eq!(*pinned, Left(6));
+    }
+
+    #[test]
+    fn derives() {
+        let set: HashSet<Either<i32, i32>> = [Left(1), Right(1), Left(1)].into_iter().collect();
+        assert_eq!(set.len(), 2);
+        assert_eq!(format!("{:?}", Left::<i32, ()>(3)), "Left(3)");
+    }
+
+    fn pick_future(left: bool) -> impl Future<Output = i32> {
+        if left {
+            Left(async { 1 })
+        } else {
+            Right(async { 2 })
+        }
+    }
+
+    #[test]
+    fn future() {
+        assert_eq!(block_on(pick_future(true)), 1);
+        assert_eq!(block_on(pick_future(false)), 2);
+    }
+
+    fn pick_poll_iter(merged: bool) -> impl AsyncIterator<Item = Either<i32, i32>> {
+        let a = async gen {
+            yield 1;
+            yield 2;
+        };
+        if merged {
+            let b = async gen {
+                yield 3;
+            };
+            Left(poll::merge::merge(a, b))
+        } else {
+            Right(async gen {
+                for await x in a {
+                    yield Left(x);
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn poll_async_iterator() {
+        block_on(async {
+            let mut result = vec![];
+            for await item in pick_poll_iter(true) {
+                result.push(item.into_inner());
+            }
+            result.sort();
+            assert_eq!(result, vec![1, 2, 3]);
+
+            let mut result = vec![];
+            for await item in pick_poll_iter(false) {
+                result.push(item.into_inner());
+            }
+            assert_eq!(result, vec![1, 2]);
+        })
+    }
+
+    #[test]
+    fn afit_async_iterator() {
+        use afit::AsyncIterator;
+
+        block_on(async {
+            for left in [true, false] {
+                let iter = if left {
+                    Left(afit::async_iter_from_iter(0..3))
+                } else {
+                    Right(afit::map::map(
+                        afit::async_iter_from_iter(0..3),
+                        async |x| x * 2,
+                    ))
+                };
+                let mut result = vec![];
+                iter.for_each(async |x| result.push(x)).await;
+                assert_eq!(result, if left { vec![0, 1, 2] } else { vec![0, 2, 4] });
+            }
+        })
+    }
+
+    #[test]
+    fn push_stream() {
+        use push::Stream;
+
+        block_on(async {
+            for left in [true, false] {
+                let stream = if left {
+                    Left(push::from_iter(0..4))
+                } else {
+                    Right(push::filter::filter(push::from_iter(0..4), async |x| {
+                        x % 2 == 1
+                    }))
+                };
+                let mut result = vec![];
+                stream.for_each(async |x| result.push(x)).await;
+                assert_eq!(result, if left { vec![0, 1, 2, 3] } else { vec![1, 3] });
+            }
+        })
+    }
+}
@@ -0,0 +1,357 @@
+//! A single-threaded executor with a run queue.
+//!
+//! Every task gets its own waker, so waking a task only puts that task back on
+//! the run queue. This is the "task per producer" alternative to polling
+//! everything from inside one future like `JoinWith` does.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::{pin, Pin},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+use crate::time::Clock;
+
+pub(crate) type Task<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// Wraps `f` in a task that stores its output for the returned [`JoinHandle`].
+pub(crate) fn task<'a, F>(f: F) -> (Task<'a>, JoinHandle<F::Output>)
+where
+    F: IntoFuture + 'a,
+    F::Output: 'a,
+{
+    let state = Rc::new(RefCell::new(JoinState::Running(None)));
+    let task_state = state.clone();
+    let f = f.into_future();
+    let task = Box::pin(async move {
+        let output = f.await;
+        let old = task_state.replace(JoinState::Finished(output));
+        if let JoinState::Running(Some(waker)) = old {
+            waker.wake();
+        }
+    });
+    (task, JoinHandle { state })
+}
+
+pub struct LocalExecutor<'a> {
+    tasks: RefCell<HashMap<usize, (Task<'a>, Arc<TaskWaker>)>>,
+    next_id: Cell<usize>,
+    shared: Arc<Shared>,
+    clock: Option<Clock>,
+}
+
+/// The part of the executor that wakers need to reach.
+struct Shared {
+    /// Ids of tasks that have been woken and need to be polled.
+    ready: Mutex<VecDeque<usize>>,
+    /// Set when the future passed to `run_until` has been woken.
+    main_woken: AtomicBool,
+    /// The thread running the executor, so wakers can unpark it.
+    thread: Thread,
+}
+
+struct TaskWaker {
+    id: usize,
+    /// Whether the task is already on the run queue, so repeated wakes don't
+    /// queue it more than once.
+    queued: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.shared.ready.lock().unwrap().push_back(self.id);
+            self.shared.thread.unpark();
+        }
+    }
+}
+
+struct MainWaker(Arc<Shared>);
+
+impl Wake for MainWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.0.main_woken.swap(true, Ordering::Release) {
+            self.0.thread.unpark();
+        }
+    }
+}
+
+impl<'a> LocalExecutor<'a> {
+    pub fn new() -> Self {
+        LocalExecutor {
+            tasks: RefCell::new(HashMap::new()),
+            next_id: Cell::new(0),
+            shared: Arc::new(Shared {
+                ready: Mutex::new(VecDeque::new()),
+                main_woken: AtomicBool::new(true),
+                thread: thread::current(),
+            }),
+            clock: None,
+        }
+    }
+
+    /// Uses `clock` for timers, jumping to the next deadline instead of
+    /// parking whenever every task is waiting.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Adds a task to the executor.
+    ///
+    /// The task does not make progress until the executor is running, i.e.
+    /// inside [`LocalExecutor::run_until`]. Dropping the returned handle
+    /// detaches the task rather than cancelling it.
+    pub fn spawn_local<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: IntoFuture + 'a,
+        F::Output: 'a,
+    {
+        let (task, handle) = task(f);
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let waker = Arc::new(TaskWaker {
+            id,
+            queued: AtomicBool::new(false),
+            shared: self.shared.clone(),
+        });
+        waker.wake_by_ref();
+        self.tasks.borrow_mut().insert(id, (task, waker));
+        handle
+    }
+
+    /// Runs spawned tasks until `f` completes.
+    ///
+    /// Tasks that are still pending when `f` finishes stay in the executor and
+    /// will resume on the next call to `run_until`.
+    pub fn run_until<F: IntoFuture>(&self, f: F) -> F::Output {
+        let main_waker = Waker::from(Arc::new(MainWaker(self.shared.clone())));
+        let mut f = pin!(f.into_future());
+        self.shared.main_woken.store(true, Ordering::Release);
+
+        loop {
+            if self.shared.main_woken.swap(false, Ordering::Acquire)
+                && let Poll::Ready(output) = f.as_mut().poll(&mut Context::from_waker(&main_waker))
+            {
+                return output;
+            }
+
+            let ready = std::mem::take(&mut *self.shared.ready.lock().unwrap());
+            for id in ready {
+                self.poll_task(id);
+            }
+
+            while !self.shared.main_woken.load(Ordering::Acquire)
+                && self.shared.ready.lock().unwrap().is_empty()
+            {
+                if let Some(clock) = &self.clock
+                    && clock.advance_to_next_deadline()
+                {
+                    continue;
+                }
+                thread::park();
+            }
+        }
+    }
+
+    /// Returns the number of tasks that have not yet completed.
+    pub fn len(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn poll_task(&self, id: usize) {
+        // Take the task out of the map while we poll it so that it can spawn
+        // new tasks without hitting a `RefCell` conflict.
+        let Some((mut task, waker)) = self.tasks.borrow_mut().remove(&id) else {
+            // The task already finished and this is a stale wakeup.
+            return;
+        };
+        waker.queued.store(false, Ordering::Release);
+        let cx_waker = Waker::from(waker.clone());
+        match task.as_mut().poll(&mut Context::from_waker(&cx_waker)) {
+            Poll::Ready(()) => {}
+            Poll::Pending => {
+                self.tasks.borrow_mut().insert(id, (task, waker));
+            }
+        }
+    }
+}
+
+impl Default for LocalExecutor<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum JoinState<T> {
+    Running(Option<Waker>),
+    Finished(T),
+    Taken,
+}
+
+/// Resolves to the output of a task spawned with
+/// [`LocalExecutor::spawn_local`] or [`Scope::spawn`].
+///
+/// [`Scope::spawn`]: crate::future_combinators::Scope::spawn
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        !matches!(*self.state.borrow(), JoinState::Running(_))
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+        match &mut *state {
+            JoinState::Running(waker) => {
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            JoinState::Finished(_) => match std::mem::replace(&mut *state, JoinState::Taken) {
+                JoinState::Finished(output) => Poll::Ready(output),
+                _ => unreachable!(),
+            },
+            JoinState::Taken => panic!("JoinHandle polled after completion"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::{Cell, RefCell},
+        future::poll_fn,
+        task::{Poll, Waker},
+    };
+
+    use super::LocalExecutor;
+
+    #[test]
+    fn spawn_and_join() {
+        let ex = LocalExecutor::new();
+        let a = ex.spawn_local(async { 1 });
+        let b = ex.spawn_local(async { 2 });
+        let result = ex.run_until(async { a.await + b.await });
+        assert_eq!(result, 3);
+        assert!(ex.is_empty());
+    }
+
+    #[test]
+    fn tasks_borrow_locals() {
+        let log = RefCell::new(vec![]);
+        let ex = LocalExecutor::new();
+        let handles = (0..3)
+            .map(|i| {
+                let log = &log;
+                ex.spawn_local(async move {
+                    log.borrow_mut().push(i);
+                })
+            })
+            .collect::<Vec<_>>();
+        ex.run_until(async {
+            for h in handles {
+                h.await;
+            }
+        });
+        let mut log = log.borrow().clone();
+        log.sort();
+        assert_eq!(log, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn spawn_from_task() {
+        let ex = LocalExecutor::new();
+        let result = ex.run_until(async {
+            let inner = ex.spawn_local(async { 5 });
+            ex.spawn_local(async { inner.await * 2 }).await
+        });
+        assert_eq!(result, 10);
+    }
+
+    #[test]
+    fn only_woken_task_is_polled() {
+        let idle_polls = Cell::new(0);
+        let idle_waker = RefCell::new(None::<Waker>);
+        let busy_polls = Cell::new(0);
+
+        let ex = LocalExecutor::new();
+        let idle = ex.spawn_local(poll_fn(|cx| {
+            idle_polls.set(idle_polls.get() + 1);
+            if busy_polls.get() < 10 {
+                *idle_waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }));
+        let busy = ex.spawn_local(poll_fn(|cx| {
+            busy_polls.set(busy_polls.get() + 1);
+            if busy_polls.get() < 10 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                idle_waker.borrow_mut().take().unwrap().wake();
+                Poll::Ready(())
+            }
+        }));
+        ex.run_until(async {
+            busy.await;
+            idle.await;
+        });
+
+        assert_eq!(busy_polls.get(), 10);
+        assert_eq!(idle_polls.get(), 2);
+    }
+
+    #[test]
+    fn unfinished_tasks_resume() {
+        let gate = Cell::new(false);
+        let gate_waker = RefCell::new(None::<Waker>);
+        let ex = LocalExecutor::new();
+        let task = ex.spawn_local(poll_fn(|cx| {
+            if gate.get() {
+                Poll::Ready(())
+            } else {
+                *gate_waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }));
+
+        // Tasks run in spawn order, so this gives the first task one poll.
+        ex.run_until(ex.spawn_local(async {}));
+        assert!(!task.is_finished());
+        assert_eq!(ex.len(), 1);
+
+        gate.set(true);
+        gate_waker.borrow_mut().take().unwrap().wake();
+        ex.run_until(task);
+        assert!(ex.is_empty());
+    }
+}
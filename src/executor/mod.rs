@@ -0,0 +1,12 @@
+//! Executors for running more than one task at a time.
+//!
+//! [`crate::block_on`] is enough to drive a single future, but to test
+//! combinators against independently scheduled tasks we need somewhere to
+//! spawn them.
+
+mod local;
+pub mod random;
+
+pub(crate) use local::{task, Task};
+pub use local::{JoinHandle, LocalExecutor};
+pub use random::RandomExecutor;
@@ -0,0 +1,296 @@
+//! A deterministic test executor that randomizes scheduling.
+//!
+//! Combinators like `poll::merge::Merge` and `race` behave differently
+//! depending on which of their inputs happens to be ready first. Our other
+//! executors only ever run one interleaving, so this one picks the next ready
+//! task at random from a seed, throws in spurious wakeups, and can wrap futures
+//! in [`Interrupt`] so they sometimes return `Pending` for no reason.
+//!
+//! Everything is driven by the seed, so a failing schedule can be replayed.
+//! Use [`sweep`] to run a test body under many seeds.
+
+use std::{
+    async_iter::AsyncIterator,
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::{pin, Pin},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use super::local::{task, JoinHandle, Task};
+use crate::{rng::Rng, time::Clock};
+
+/// Id used for the future passed to `run_until`.
+const MAIN: usize = usize::MAX;
+
+/// One in this many polls is preceded by a spurious wakeup.
+const SPURIOUS_WAKE_ODDS: u64 = 8;
+
+/// One in this many polls of an [`Interrupt`] returns `Pending` without
+/// polling the inner future.
+const INTERRUPT_ODDS: u64 = 4;
+
+pub struct RandomExecutor<'a> {
+    seed: u64,
+    rng: Rc<Rng>,
+    // A `BTreeMap` rather than a `HashMap` so that picking a task for a
+    // spurious wakeup doesn't depend on hash randomization.
+    tasks: RefCell<BTreeMap<usize, Task<'a>>>,
+    next_id: Cell<usize>,
+    ready: Arc<Mutex<Vec<usize>>>,
+    clock: Option<Clock>,
+}
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut ready = self.ready.lock().unwrap();
+        if !ready.contains(&self.id) {
+            ready.push(self.id);
+        }
+    }
+}
+
+impl<'a> RandomExecutor<'a> {
+    pub fn new(seed: u64) -> Self {
+        RandomExecutor {
+            seed,
+            rng: Rc::new(Rng::new(seed)),
+            tasks: RefCell::new(BTreeMap::new()),
+            next_id: Cell::new(0),
+            ready: Arc::new(Mutex::new(Vec::new())),
+            clock: None,
+        }
+    }
+
+    /// Uses `clock` for timers, jumping to the next deadline whenever no tasks
+    /// are ready.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn spawn_local<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: IntoFuture + 'a,
+        F::Output: 'a,
+    {
+        let (task, handle) = task(f);
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.tasks.borrow_mut().insert(id, task);
+        self.waker(id).wake();
+        handle
+    }
+
+    /// Wraps `inner` so that polling it sometimes returns `Pending` first.
+    ///
+    /// The wrapper wakes itself whenever it does this, so a correct combinator
+    /// will always poll it again.
+    pub fn interrupt<T>(&self, inner: T) -> Interrupt<T> {
+        Interrupt {
+            inner,
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Runs spawned tasks in a random order until `f` completes.
+    ///
+    /// Since nothing outside this executor can wake its tasks, running out of
+    /// ready tasks and timers before `f` completes means some future lost a
+    /// wakeup. We panic instead of hanging in that case.
+    pub fn run_until<F: IntoFuture>(&self, f: F) -> F::Output {
+        let mut f = pin!(f.into_future());
+        self.waker(MAIN).wake();
+
+        loop {
+            if self.ready.lock().unwrap().is_empty()
+                && let Some(clock) = &self.clock
+            {
+                clock.advance_to_next_deadline();
+            }
+
+            let id = {
+                let mut ready = self.ready.lock().unwrap();
+                if ready.is_empty() {
+                    panic!(
+                        "deadlock with seed {}: no tasks are ready but the main future has not completed",
+                        self.seed
+                    );
+                }
+                let i = self.rng.below(ready.len());
+                ready.swap_remove(i)
+            };
+
+            if self.rng.one_in(SPURIOUS_WAKE_ODDS) {
+                self.spurious_wake();
+            }
+
+            let waker = self.waker(id);
+            let mut cx = Context::from_waker(&waker);
+            if id == MAIN {
+                if let Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            } else {
+                let Some(mut task) = self.tasks.borrow_mut().remove(&id) else {
+                    continue;
+                };
+                if task.as_mut().poll(&mut cx).is_pending() {
+                    self.tasks.borrow_mut().insert(id, task);
+                }
+            }
+        }
+    }
+
+    fn spurious_wake(&self) {
+        let tasks = self.tasks.borrow();
+        let i = self.rng.below(tasks.len() + 1);
+        let id = tasks.keys().nth(i).copied().unwrap_or(MAIN);
+        self.waker(id).wake();
+    }
+
+    fn waker(&self, id: usize) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            id,
+            ready: self.ready.clone(),
+        }))
+    }
+}
+
+/// Runs `f` once for each seed in `0..schedules`.
+///
+/// If `f` panics, the failing seed is printed before the panic continues so
+/// the schedule can be replayed with `RandomExecutor::new(seed)`. Setting the
+/// `SCHEDULE_SEED` environment variable runs only that seed.
+pub fn sweep(schedules: u64, mut f: impl FnMut(u64)) {
+    let seeds = match std::env::var("SCHEDULE_SEED") {
+        Ok(seed) => {
+            let seed = seed.parse().expect("SCHEDULE_SEED must be a u64");
+            seed..seed + 1
+        }
+        Err(_) => 0..schedules,
+    };
+    for seed in seeds {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(seed))) {
+            eprintln!("schedule failed with seed {seed}; rerun with SCHEDULE_SEED={seed}");
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+/// Returned by [`RandomExecutor::interrupt`].
+pub struct Interrupt<T> {
+    inner: T,
+    rng: Rc<Rng>,
+}
+
+impl<T> Interrupt<T> {
+    /// Decides whether to return `Pending` instead of polling the inner value.
+    fn interrupt(&self, cx: &mut Context<'_>) -> bool {
+        if self.rng.one_in(INTERRUPT_ODDS) {
+            cx.waker().wake_by_ref();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<F: Future> Future for Interrupt<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.interrupt(cx) {
+            return Poll::Pending;
+        }
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }.poll(cx)
+    }
+}
+
+impl<I: AsyncIterator> AsyncIterator for Interrupt<I> {
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.interrupt(cx) {
+            return Poll::Pending;
+        }
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }.poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::RefCell,
+        future::poll_fn,
+        panic::{self, AssertUnwindSafe},
+        task::Poll,
+    };
+
+    use super::{sweep, RandomExecutor};
+
+    fn spawn_order(seed: u64) -> Vec<usize> {
+        let log = RefCell::new(vec![]);
+        let ex = RandomExecutor::new(seed);
+        let handles = (0..8)
+            .map(|i| {
+                let log = &log;
+                ex.spawn_local(async move { log.borrow_mut().push(i) })
+            })
+            .collect::<Vec<_>>();
+        ex.run_until(async {
+            for h in handles {
+                h.await;
+            }
+        });
+        log.take()
+    }
+
+    #[test]
+    fn same_seed_same_schedule() {
+        for seed in 0..16 {
+            assert_eq!(spawn_order(seed), spawn_order(seed));
+        }
+    }
+
+    #[test]
+    fn schedules_vary() {
+        let first = spawn_order(0);
+        assert!((1..16).any(|seed| spawn_order(seed) != first));
+    }
+
+    #[test]
+    fn interrupted_future_completes() {
+        sweep(1000, |seed| {
+            let ex = RandomExecutor::new(seed);
+            let result = ex.run_until(ex.interrupt(async { ex.spawn_local(async { 7 }).await }));
+            assert_eq!(result, 7);
+        });
+    }
+
+    #[test]
+    fn lost_wakeup_is_a_deadlock() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let ex = RandomExecutor::new(0);
+            ex.run_until(poll_fn(|_cx| Poll::<()>::Pending));
+        }));
+        assert!(result.is_err());
+    }
+}
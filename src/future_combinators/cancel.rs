@@ -0,0 +1,260 @@
+//! A token for asking futures to shut down cooperatively.
+//!
+//! Dropping a future stops it dead, wherever it happens to be. A
+//! [`CancellationToken`] lets the owner ask first: the future watches
+//! [`CancellationToken::cancelled`] and cleans up on its own. Combinators like
+//! [`JoinFuture::shutdown_with`](super::JoinFuture::shutdown_with) fire a
+//! token and then allow a grace period before dropping whatever is left.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll, Waker},
+};
+
+/// A shared flag that can be set once, with a way to wait for it.
+///
+/// Clones share the same flag. A child token is cancelled along with its
+/// parent, but cancelling the child leaves the parent alone.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    node: Arc<Node>,
+}
+
+#[derive(Default)]
+struct Node {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    /// The wakers of pending [`WaitForCancellation`]s, keyed so each one can
+    /// take its waker back out when it's dropped.
+    wakers: BTreeMap<usize, Waker>,
+    next_key: usize,
+    children: Vec<Weak<Node>>,
+}
+
+impl Node {
+    fn cancel(&self) {
+        let (wakers, children) = {
+            let mut state = self.state.lock().unwrap();
+            if state.cancelled {
+                return;
+            }
+            state.cancelled = true;
+            (
+                std::mem::take(&mut state.wakers),
+                std::mem::take(&mut state.children),
+            )
+        };
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token that is cancelled when this one is, but can also be
+    /// cancelled on its own.
+    pub fn child_token(&self) -> CancellationToken {
+        let child = CancellationToken::new();
+        let mut state = self.node.state.lock().unwrap();
+        if state.cancelled {
+            child.node.state.lock().unwrap().cancelled = true;
+        } else {
+            // Forget children that have already been dropped.
+            state.children.retain(|child| child.strong_count() > 0);
+            state.children.push(Arc::downgrade(&child.node));
+        }
+        child
+    }
+
+    /// Cancels this token and all of its children, waking everything that is
+    /// waiting on them.
+    pub fn cancel(&self) {
+        self.node.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.node.state.lock().unwrap().cancelled
+    }
+
+    /// Resolves once the token is cancelled.
+    pub fn cancelled(&self) -> WaitForCancellation<'_> {
+        WaitForCancellation {
+            token: self,
+            key: None,
+        }
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Returned by [`CancellationToken::cancelled`].
+pub struct WaitForCancellation<'a> {
+    token: &'a CancellationToken,
+    /// Where our waker is registered, once we've been polled.
+    key: Option<usize>,
+}
+
+impl Future for WaitForCancellation<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let mut state = this.token.node.state.lock().unwrap();
+        if state.cancelled {
+            // `cancel` already took our waker.
+            this.key = None;
+            return Poll::Ready(());
+        }
+        match this.key.and_then(|key| state.wakers.get_mut(&key)) {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => {
+                let key = state.next_key;
+                state.next_key += 1;
+                state.wakers.insert(key, cx.waker().clone());
+                this.key = Some(key);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for WaitForCancellation<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.token.node.state.lock().unwrap().wakers.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        future::Future,
+        pin::pin,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        task::{Context, Wake, Waker},
+    };
+
+    use super::CancellationToken;
+    use crate::{executor::LocalExecutor, future_combinators::race};
+
+    #[test]
+    fn wakes_waiters() {
+        let token = CancellationToken::new();
+        let woken = Cell::new(0);
+        let ex = LocalExecutor::new();
+        for _ in 0..3 {
+            ex.spawn_local(async {
+                token.cancelled().await;
+                woken.set(woken.get() + 1);
+            });
+        }
+        ex.run_until(async {
+            assert!(!token.is_cancelled());
+            token.cancel();
+            // Let the waiters run.
+            ex.spawn_local(async {}).await;
+        });
+        assert!(token.is_cancelled());
+        assert_eq!(woken.get(), 3);
+    }
+
+    #[test]
+    fn child_tokens() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        let sibling = parent.child_token();
+
+        sibling.cancel();
+        assert!(!parent.is_cancelled());
+        assert!(!child.is_cancelled());
+
+        parent.cancel();
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+        assert!(parent.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn clones_share_state() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        let ex = LocalExecutor::new();
+        let result = ex.run_until(race(token.cancelled(), async { clone.cancel() }));
+        assert!(result.is_right());
+        assert!(token.is_cancelled());
+    }
+
+    /// Remembers whether it was woken.
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn dropped_waiters_unregister() {
+        let token = CancellationToken::new();
+        let registered = || token.node.state.lock().unwrap().wakers.len();
+
+        let mut kept = pin!(token.cancelled());
+        let flag = Arc::new(Flag::default());
+        let waker = Waker::from(flag.clone());
+        assert!(kept
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+
+        for _ in 0..100 {
+            // A fresh waker each time, so none of them look like the others.
+            let waker = Waker::from(Arc::new(Flag::default()));
+            let mut waiter = pin!(token.cancelled());
+            assert!(waiter
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending());
+            assert!(waiter
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending());
+        }
+        assert_eq!(registered(), 1);
+
+        token.cancel();
+        assert!(flag.0.load(Ordering::Relaxed));
+        assert!(kept
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready());
+        assert_eq!(registered(), 0);
+    }
+}
@@ -0,0 +1,100 @@
+//! Strategies for deciding which of two branches gets polled first.
+//!
+//! When both branches of a `race` or a `merge` are ready at once, whichever is
+//! polled first wins. Always picking the same one starves the other, so by
+//! default the two take turns, but [`Fairness`] lets the caller pick.
+
+use crate::rng::Rng;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fairness {
+    /// The left branch always goes first. The right branch only gets an item
+    /// through when the left one has nothing to give, which suits priority
+    /// channels.
+    Biased,
+    /// The branches take turns going first.
+    #[default]
+    RoundRobin,
+    /// Out of every `left + right` polls, the left branch goes first `left`
+    /// times and the right branch `right` times.
+    ///
+    /// Both weights being zero is a bug, and the combinator using it panics.
+    Weighted(u32, u32),
+    /// Each poll picks a branch at random, using a generator seeded with the
+    /// given value.
+    Random(u64),
+}
+
+/// The running state for a [`Fairness`] strategy.
+pub(crate) struct Turns {
+    fairness: Fairness,
+    /// How many turns have been handed out so far.
+    count: u64,
+    rng: Option<Rng>,
+}
+
+impl Turns {
+    pub(crate) fn new(fairness: Fairness) -> Self {
+        if let Fairness::Weighted(left, right) = fairness {
+            assert!(
+                left > 0 || right > 0,
+                "Fairness::Weighted needs a nonzero weight"
+            );
+        }
+        Turns {
+            fairness,
+            count: 0,
+            rng: match fairness {
+                Fairness::Random(seed) => Some(Rng::new(seed)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Whether the left branch goes first on this poll.
+    pub(crate) fn left_first(&mut self) -> bool {
+        let count = self.count;
+        self.count += 1;
+        match self.fairness {
+            Fairness::Biased => true,
+            Fairness::RoundRobin => count.is_multiple_of(2),
+            Fairness::Weighted(left, right) => {
+                count % (u64::from(left) + u64::from(right)) < u64::from(left)
+            }
+            Fairness::Random(_) => self.rng.as_ref().unwrap().one_in(2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Fairness, Turns};
+
+    fn first_turns(fairness: Fairness, n: usize) -> Vec<bool> {
+        let mut turns = Turns::new(fairness);
+        (0..n).map(|_| turns.left_first()).collect()
+    }
+
+    #[test]
+    fn turn_order() {
+        assert_eq!(first_turns(Fairness::Biased, 4), vec![true; 4]);
+        assert_eq!(
+            first_turns(Fairness::RoundRobin, 4),
+            vec![true, false, true, false]
+        );
+        assert_eq!(
+            first_turns(Fairness::Weighted(3, 1), 8),
+            vec![true, true, true, false, true, true, true, false]
+        );
+
+        let random = first_turns(Fairness::Random(7), 100);
+        assert!(random.contains(&true) && random.contains(&false));
+        assert_eq!(random, first_turns(Fairness::Random(7), 100));
+    }
+
+    #[test]
+    #[should_panic(expected = "nonzero weight")]
+    fn weighted_needs_a_nonzero_weight() {
+        Turns::new(Fairness::Weighted(0, 0));
+    }
+}
@@ -2,19 +2,38 @@
 //! task with some number of side-effect tasks that run concurrently with the
 //! root.
 //!
-//! One downside is that when the root task completes, all the other tasks are
-//! cancelled. Things also probably break if one of the other tasks finishes
-//! first.
+//! By default, when the root task completes, all the other tasks are
+//! cancelled. Use [`JoinFuture::wait_all`] to keep going until the side tasks
+//! finish too, or [`JoinFuture::keep_remaining`] to get the unfinished side
+//! tasks back along with the root's output.
 //!
-//! This is also probably horribly wrong if we ever had non-trivial wakers.
+//! Side tasks added with [`JoinFuture::try_with`] return a `Result`. The first
+//! `Err`, from one of them or from the root, ends the join right away and
+//! becomes its output.
+//!
+//! A branch that panics normally takes the whole join down with it. Use
+//! [`JoinFuture::catch_unwind`] to catch the panic instead, so the other
+//! branches keep running and the payload is handed back at the end.
+//!
+//! [`JoinFuture::shutdown_with`] fires a [`CancellationToken`] when the root
+//! completes and gives the side tasks a grace period to wrap up.
+//!
+//! Each branch gets its own waker, so a wakeup only polls the branches that
+//! were actually woken.
 
 use std::{
+    any::Any,
+    fmt,
     future::poll_fn,
+    panic::{self, AssertUnwindSafe},
     pin::{pin, Pin},
     task::{Context, Poll},
 };
 
+use super::{race, ready::ReadySet, CancellationToken};
+
 pub trait JoinFuture: IntoFuture {
+    /// The number of branches, including the root.
     fn size(&self) -> usize;
 
     fn with<F: IntoFuture<Output = ()>>(self, f: F) -> impl JoinFuture<Output = Self::Output>
@@ -24,10 +43,101 @@ pub trait JoinFuture: IntoFuture {
         JoinWith {
             future: f.into_future(),
             next: self,
+            done: false,
+        }
+    }
+
+    /// Adds a side task that can fail, for joins whose root returns a
+    /// `Result` with the same error type.
+    ///
+    /// If the side task returns `Err`, the join resolves to that error
+    /// immediately. A root that returns `Err` does the same. The branches that
+    /// are still running are then dropped newest first, ending with the root.
+    fn try_with<F, T, E>(self, f: F) -> impl JoinFuture<Output = Self::Output>
+    where
+        Self: Sized + JoinFuture<Output = Result<T, E>>,
+        F: IntoFuture<Output = Result<(), E>>,
+    {
+        TryWith {
+            future: f.into_future(),
+            next: self,
+            done: false,
+            error: None,
         }
     }
 
-    fn poll_depth(self: Pin<&mut Self>, cx: &mut Context<'_>, depth: usize) -> Poll<Self::Output>;
+    /// Keeps polling the side tasks after the root completes, and only
+    /// resolves once every branch has finished.
+    fn wait_all(self) -> WaitAll<Self>
+    where
+        Self: Sized,
+    {
+        WaitAll { join: self }
+    }
+
+    /// Resolves as soon as the root completes, handing back the side tasks
+    /// that have not finished yet.
+    ///
+    /// The returned [`Remaining`] is a future that drives the leftover side
+    /// tasks to completion. Dropping it cancels them.
+    fn keep_remaining(self) -> KeepRemaining<Self>
+    where
+        Self: Sized,
+    {
+        KeepRemaining { join: self }
+    }
+
+    /// Cancels `token` once the root completes, then keeps polling the side
+    /// tasks until they have all finished or `grace` completes, whichever
+    /// comes first. Whatever is left after that is dropped.
+    ///
+    /// `grace` isn't polled until the root completes, so an `async` block
+    /// that sleeps starts its timer then.
+    fn shutdown_with<G>(self, token: CancellationToken, grace: G) -> Shutdown<Self, G::IntoFuture>
+    where
+        Self: Sized,
+        G: IntoFuture<Output = ()>,
+    {
+        Shutdown {
+            join: self,
+            token,
+            grace: grace.into_future(),
+        }
+    }
+
+    /// Catches panics from the branches instead of letting them unwind
+    /// through the join.
+    ///
+    /// A branch that panics counts as finished and the rest keep running. If
+    /// any branch panicked, the join resolves to a [`JoinPanic`] holding the
+    /// payloads, along with the root's output if the root didn't panic.
+    fn catch_unwind(self) -> CatchUnwind<Self>
+    where
+        Self: Sized,
+    {
+        CatchUnwind {
+            join: self,
+            until: Until::Root,
+        }
+    }
+
+    /// Polls the branch at `depth`, where the root is at `size() - 1`.
+    ///
+    /// Returns `Ready` once that branch has finished. Finished branches are
+    /// not polled again.
+    fn poll_depth(self: Pin<&mut Self>, cx: &mut Context<'_>, depth: usize) -> Poll<()>;
+
+    /// Whether the branch at `depth` has finished.
+    fn is_done(&self, depth: usize) -> bool;
+
+    /// The root's output, if it has finished and the output hasn't been taken.
+    fn output(&self) -> Option<&Self::Output>;
+
+    /// Whether a fallible branch has failed, which ends the join early.
+    fn is_failed(&self) -> bool;
+
+    /// Takes the root's output. Panics if the root has not finished.
+    fn take_output(self: Pin<&mut Self>) -> Self::Output;
 }
 
 pub fn join<F>(f: F) -> JoinRoot<F::IntoFuture>
@@ -36,11 +146,15 @@ where
 {
     JoinRoot {
         future: f.into_future(),
+        output: None,
+        done: false,
     }
 }
 
 pub struct JoinRoot<F: Future> {
     future: F,
+    output: Option<F::Output>,
+    done: bool,
 }
 
 impl<F> JoinFuture for JoinRoot<F>
@@ -51,16 +165,51 @@ where
         1
     }
 
-    fn poll_depth(self: Pin<&mut Self>, cx: &mut Context<'_>, depth: usize) -> Poll<Self::Output> {
+    fn poll_depth(self: Pin<&mut Self>, cx: &mut Context<'_>, depth: usize) -> Poll<()> {
         assert_eq!(depth, 0);
 
-        unsafe { self.map_unchecked_mut(|this| &mut this.future) }.poll(cx)
+        unsafe {
+            let this = self.get_unchecked_mut();
+            if this.done {
+                return Poll::Ready(());
+            }
+            match Pin::new_unchecked(&mut this.future).poll(cx) {
+                Poll::Ready(output) => {
+                    this.output = Some(output);
+                    this.done = true;
+                    Poll::Ready(())
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    fn is_done(&self, depth: usize) -> bool {
+        assert_eq!(depth, 0);
+        self.done
+    }
+
+    fn output(&self) -> Option<&Self::Output> {
+        self.output.as_ref()
+    }
+
+    fn is_failed(&self) -> bool {
+        false
+    }
+
+    fn take_output(self: Pin<&mut Self>) -> Self::Output {
+        unsafe { self.get_unchecked_mut() }
+            .output
+            .take()
+            .expect("join root has not finished")
     }
 }
 
 pub struct JoinWith<F: Future, G: JoinFuture> {
     future: F,
     next: G,
+    /// Set once `future` completes so we don't poll it again.
+    done: bool,
 }
 
 impl<F, G> JoinFuture for JoinWith<F, G>
@@ -72,36 +221,250 @@ where
         1 + self.next.size()
     }
 
-    fn poll_depth(self: Pin<&mut Self>, cx: &mut Context<'_>, depth: usize) -> Poll<Self::Output> {
+    fn poll_depth(self: Pin<&mut Self>, cx: &mut Context<'_>, depth: usize) -> Poll<()> {
+        unsafe {
+            let this = self.get_unchecked_mut();
+            if depth == 0 {
+                if this.done {
+                    return Poll::Ready(());
+                }
+                let poll = Pin::new_unchecked(&mut this.future).poll(cx);
+                this.done = poll.is_ready();
+                poll
+            } else {
+                Pin::new_unchecked(&mut this.next).poll_depth(cx, depth - 1)
+            }
+        }
+    }
+
+    fn is_done(&self, depth: usize) -> bool {
         if depth == 0 {
-            if let Poll::Ready(()) =
-                unsafe { self.map_unchecked_mut(|this| &mut this.future).poll(cx) }
-            {
-                // Wake because while this future finished, we still need to
-                // poll so the others can finish.
-                cx.waker().wake_by_ref();
+            self.done
+        } else {
+            self.next.is_done(depth - 1)
+        }
+    }
+
+    fn output(&self) -> Option<&Self::Output> {
+        self.next.output()
+    }
+
+    fn is_failed(&self) -> bool {
+        self.next.is_failed()
+    }
+
+    fn take_output(self: Pin<&mut Self>) -> Self::Output {
+        unsafe { self.map_unchecked_mut(|this| &mut this.next) }.take_output()
+    }
+}
+
+/// A side task added with [`JoinFuture::try_with`].
+pub struct TryWith<F, G, E> {
+    future: F,
+    next: G,
+    done: bool,
+    /// The error the side task returned, which becomes the join's output.
+    error: Option<E>,
+}
+
+impl<F, G, T, E> JoinFuture for TryWith<F, G, E>
+where
+    F: Future<Output = Result<(), E>>,
+    G: JoinFuture<Output = Result<T, E>>,
+{
+    fn size(&self) -> usize {
+        1 + self.next.size()
+    }
+
+    fn poll_depth(self: Pin<&mut Self>, cx: &mut Context<'_>, depth: usize) -> Poll<()> {
+        unsafe {
+            let this = self.get_unchecked_mut();
+            if depth == 0 {
+                if this.done {
+                    return Poll::Ready(());
+                }
+                match Pin::new_unchecked(&mut this.future).poll(cx) {
+                    Poll::Ready(result) => {
+                        this.error = result.err();
+                        this.done = true;
+                        Poll::Ready(())
+                    }
+                    Poll::Pending => Poll::Pending,
+                }
+            } else {
+                Pin::new_unchecked(&mut this.next).poll_depth(cx, depth - 1)
             }
-            Poll::Pending
+        }
+    }
+
+    fn is_done(&self, depth: usize) -> bool {
+        if depth == 0 {
+            self.done
         } else {
-            unsafe {
-                self.map_unchecked_mut(|this| &mut this.next)
-                    .poll_depth(cx, depth - 1)
+            self.next.is_done(depth - 1)
+        }
+    }
+
+    fn output(&self) -> Option<&Self::Output> {
+        self.next.output()
+    }
+
+    fn is_failed(&self) -> bool {
+        self.error.is_some() || self.next.is_failed() || matches!(self.output(), Some(Err(_)))
+    }
+
+    fn take_output(self: Pin<&mut Self>) -> Self::Output {
+        let this = unsafe { self.get_unchecked_mut() };
+        match this.error.take() {
+            Some(error) => Err(error),
+            None => unsafe { Pin::new_unchecked(&mut this.next) }.take_output(),
+        }
+    }
+}
+
+/// When a join counts as finished.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Until {
+    /// As soon as the root completes.
+    Root,
+    /// Once every branch has completed.
+    All,
+}
+
+/// Moves `start` to the next unfinished branch, so each live branch takes a
+/// turn going first. Finished branches don't get a turn.
+fn next_start(size: usize, is_done: impl Fn(usize) -> bool, start: &mut usize) -> usize {
+    for _ in 0..size {
+        *start = (*start + size - 1) % size;
+        if !is_done(*start) {
+            break;
+        }
+    }
+    *start
+}
+
+/// A caught panic payload, with the position of the branch that panicked.
+type Payload = (usize, Box<dyn Any + Send>);
+
+/// Which branches of a [`JoinFuture::catch_unwind`] join have panicked.
+struct Panics {
+    /// Indexed by depth.
+    panicked: Vec<bool>,
+    payloads: Vec<Payload>,
+}
+
+impl Panics {
+    /// Whether the branch at `depth` has finished, by completing or panicking.
+    fn is_done<F: JoinFuture>(panics: &Option<Panics>, this: &F, depth: usize) -> bool {
+        this.is_done(depth) || panics.as_ref().is_some_and(|panics| panics.panicked[depth])
+    }
+
+    /// Polls the branch at `depth`, catching a panic if `panics` is set.
+    fn poll_depth<F: JoinFuture>(
+        panics: &mut Option<Panics>,
+        this: Pin<&mut F>,
+        cx: &mut Context<'_>,
+        depth: usize,
+    ) -> Poll<()> {
+        let Some(panics) = panics else {
+            return this.poll_depth(cx, depth);
+        };
+        let size = this.size();
+        // The branch that panicked is never polled again, so it doesn't matter
+        // what state the panic left it in.
+        match panic::catch_unwind(AssertUnwindSafe(|| this.poll_depth(cx, depth))) {
+            Ok(poll) => poll,
+            Err(payload) => {
+                panics.panicked[depth] = true;
+                panics.payloads.push((size - 1 - depth, payload));
+                Poll::Ready(())
             }
         }
     }
 }
 
-async fn run_join<F: JoinFuture>(f: F) -> F::Output {
-    let mut i = f.size();
-    let mut this = pin!(f);
-    poll_fn(move |cx| {
-        i -= 1;
-        if i == 0 {
-            i = this.size();
+/// The state we keep while running a join, apart from the branches themselves.
+struct Driver {
+    /// Gives each branch its own waker and records which ones were woken.
+    ready: ReadySet,
+    start: usize,
+    /// Set if we're catching panics.
+    panics: Option<Panics>,
+}
+
+impl Driver {
+    fn new(size: usize) -> Self {
+        Driver {
+            ready: ReadySet::new(size),
+            start: size,
+            panics: None,
+        }
+    }
+
+    fn catching_panics(size: usize) -> Self {
+        Driver {
+            panics: Some(Panics {
+                panicked: vec![false; size],
+                payloads: vec![],
+            }),
+            ..Driver::new(size)
+        }
+    }
+
+    /// Polls the unfinished branches that were woken since the last call.
+    ///
+    /// Returns `Ready` once the join is finished according to `until`, or as
+    /// soon as a fallible branch fails.
+    fn poll<F: JoinFuture>(
+        &mut self,
+        mut this: Pin<&mut F>,
+        cx: &mut Context<'_>,
+        until: Until,
+    ) -> Poll<()> {
+        let size = this.size();
+        let root = size - 1;
+        self.ready.register(cx.waker());
+        self.ready.take();
+        let start = next_start(
+            size,
+            |depth| Panics::is_done(&self.panics, &*this, depth),
+            &mut self.start,
+        );
+        let mut woken = self.ready.woken_from(start);
+        for depth in woken.by_ref() {
+            if Panics::is_done(&self.panics, &*this, depth) {
+                continue;
+            }
+            let mut cx = Context::from_waker(self.ready.waker(depth));
+            if Panics::poll_depth(&mut self.panics, this.as_mut(), &mut cx, depth).is_ready()
+                && ((depth == root && until == Until::Root) || this.is_failed())
+            {
+                // Don't bother polling branches that may be about to be
+                // cancelled, but remember that they were woken in case
+                // they get handed back by `keep_remaining`.
+                for depth in woken {
+                    self.ready.mark(depth);
+                }
+                return Poll::Ready(());
+            }
         }
-        this.as_mut().poll_depth(cx, i - 1)
-    })
-    .await
+        let finished = match until {
+            Until::Root => Panics::is_done(&self.panics, &*this, root),
+            Until::All => (0..size).all(|depth| Panics::is_done(&self.panics, &*this, depth)),
+        };
+        if finished {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+async fn run_join<F: JoinFuture>(f: F, until: Until) -> F::Output {
+    let mut driver = Driver::new(f.size());
+    let mut this = pin!(f);
+    poll_fn(|cx| driver.poll(this.as_mut(), cx, until)).await;
+    this.take_output()
 }
 
 impl<F> IntoFuture for JoinRoot<F>
@@ -113,7 +476,7 @@ where
     type IntoFuture = impl Future<Output = Self::Output>;
 
     fn into_future(self) -> Self::IntoFuture {
-        run_join(self)
+        run_join(self, Until::Root)
     }
 }
 
@@ -127,38 +490,666 @@ where
     type IntoFuture = impl Future<Output = Self::Output>;
 
     fn into_future(self) -> Self::IntoFuture {
-        run_join(self)
+        run_join(self, Until::Root)
+    }
+}
+
+impl<F, G, T, E> IntoFuture for TryWith<F, G, E>
+where
+    F: Future<Output = Result<(), E>>,
+    G: JoinFuture<Output = Result<T, E>>,
+{
+    type Output = G::Output;
+
+    type IntoFuture = impl Future<Output = Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        run_join(self, Until::Root)
+    }
+}
+
+/// Returned by [`JoinFuture::wait_all`].
+pub struct WaitAll<J> {
+    join: J,
+}
+
+impl<J: JoinFuture> WaitAll<J> {
+    /// Like [`JoinFuture::catch_unwind`], but still waits for every branch.
+    pub fn catch_unwind(self) -> CatchUnwind<J> {
+        CatchUnwind {
+            join: self.join,
+            until: Until::All,
+        }
+    }
+}
+
+impl<J: JoinFuture> IntoFuture for WaitAll<J> {
+    type Output = J::Output;
+
+    type IntoFuture = impl Future<Output = Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        run_join(self.join, Until::All)
+    }
+}
+
+/// Returned by [`JoinFuture::catch_unwind`] and [`WaitAll::catch_unwind`].
+pub struct CatchUnwind<J> {
+    join: J,
+    until: Until,
+}
+
+impl<J: JoinFuture> IntoFuture for CatchUnwind<J> {
+    type Output = Result<J::Output, JoinPanic<J::Output>>;
+
+    type IntoFuture = impl Future<Output = Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        async move {
+            let root = self.join.size() - 1;
+            let mut driver = Driver::catching_panics(self.join.size());
+            let mut this = pin!(self.join);
+            poll_fn(|cx| driver.poll(this.as_mut(), cx, self.until)).await;
+            let panics = driver.panics.unwrap();
+            let output = (!panics.panicked[root]).then(|| this.take_output());
+            if panics.payloads.is_empty() {
+                Ok(output.unwrap())
+            } else {
+                Err(JoinPanic {
+                    output,
+                    payloads: panics.payloads,
+                })
+            }
+        }
+    }
+}
+
+/// The panics caught by a [`JoinFuture::catch_unwind`] join.
+///
+/// Branches are numbered in the order they were added, starting with the root
+/// at 0.
+pub struct JoinPanic<T> {
+    output: Option<T>,
+    payloads: Vec<Payload>,
+}
+
+impl<T> JoinPanic<T> {
+    /// Whether the root was one of the branches that panicked.
+    pub fn root_panicked(&self) -> bool {
+        self.output.is_none()
+    }
+
+    /// The root's output, unless the root panicked.
+    pub fn into_output(self) -> Option<T> {
+        self.output
+    }
+
+    /// The branches that panicked, in the order they panicked.
+    pub fn branches(&self) -> impl Iterator<Item = usize> + '_ {
+        self.payloads.iter().map(|(branch, _)| *branch)
+    }
+
+    /// The panic payloads, with the branch each one came from.
+    pub fn into_payloads(self) -> Vec<(usize, Box<dyn Any + Send>)> {
+        self.payloads
+    }
+
+    /// Continues unwinding with the first panic that was caught.
+    pub fn resume(self) -> ! {
+        let (_, payload) = self.payloads.into_iter().next().unwrap();
+        panic::resume_unwind(payload)
+    }
+}
+
+impl<T> fmt::Debug for JoinPanic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinPanic")
+            .field("branches", &self.branches().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returned by [`JoinFuture::keep_remaining`].
+pub struct KeepRemaining<J> {
+    join: J,
+}
+
+impl<J: JoinFuture> IntoFuture for KeepRemaining<J> {
+    type Output = (J::Output, Remaining<J>);
+
+    type IntoFuture = impl Future<Output = Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        async move {
+            // Boxed so we can hand the branches back without moving them.
+            let mut remaining = Remaining {
+                driver: Driver::new(self.join.size()),
+                join: Box::pin(self.join),
+            };
+            poll_fn(|cx| remaining.poll_until(cx, Until::Root)).await;
+            let output = remaining.join.as_mut().take_output();
+            (output, remaining)
+        }
+    }
+}
+
+/// Returned by [`JoinFuture::shutdown_with`].
+pub struct Shutdown<J, G> {
+    join: J,
+    token: CancellationToken,
+    grace: G,
+}
+
+impl<J, G> IntoFuture for Shutdown<J, G>
+where
+    J: JoinFuture,
+    G: Future<Output = ()>,
+{
+    type Output = J::Output;
+
+    type IntoFuture = impl Future<Output = Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        async move {
+            let (output, remaining) = KeepRemaining { join: self.join }.await;
+            self.token.cancel();
+            race(remaining, self.grace).await;
+            output
+        }
+    }
+}
+
+/// The side tasks that were still running when the root of a
+/// [`JoinFuture::keep_remaining`] join completed.
+///
+/// Awaiting this runs them to completion. Dropping it cancels them.
+pub struct Remaining<J> {
+    join: Pin<Box<J>>,
+    driver: Driver,
+}
+
+impl<J: JoinFuture> Remaining<J> {
+    /// The number of side tasks that have not finished.
+    pub fn len(&self) -> usize {
+        (0..self.join.size())
+            .filter(|&depth| !self.join.is_done(depth))
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn poll_until(&mut self, cx: &mut Context<'_>, until: Until) -> Poll<()> {
+        self.driver.poll(self.join.as_mut(), cx, until)
+    }
+}
+
+impl<J: JoinFuture> Future for Remaining<J> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.get_mut().poll_until(cx, Until::All)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::{cell::RefCell, future::poll_fn, task::Poll};
+    use std::{
+        cell::{Cell, RefCell},
+        future::{poll_fn, Future},
+        pin::pin,
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
 
-    use crate::block_on;
+    use crate::{
+        block_on,
+        executor::{random::sweep, LocalExecutor, RandomExecutor},
+        future_combinators::CancellationToken,
+        instrument::Instrumented,
+        time::Clock,
+    };
 
-    use super::{join, JoinFuture};
+    use super::{join, next_start, JoinFuture};
 
     #[test]
     fn join_futures() {
         let cell1 = RefCell::new(0);
         let cell2 = RefCell::new(0);
-        let f = join(poll_fn(|_cx| {
-            if *cell1.borrow() < 10 && *cell2.borrow() < 10 {
+        let root_waker = RefCell::new(None::<Waker>);
+        let f = join(poll_fn(|cx| {
+            if *cell1.borrow() < 10 || *cell2.borrow() < 10 {
+                *root_waker.borrow_mut() = Some(cx.waker().clone());
                 Poll::Pending
             } else {
                 Poll::Ready(*cell1.borrow() + *cell2.borrow())
             }
         }))
-        .with(poll_fn(|_cx| {
+        .with(poll_fn(|cx| {
             *cell1.borrow_mut() += 1;
-            Poll::Ready(())
+            if let Some(waker) = root_waker.borrow_mut().take() {
+                waker.wake();
+            }
+            if *cell1.borrow() < 10 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
         }))
-        .with(poll_fn(|_cx| {
+        .with(poll_fn(|cx| {
             *cell2.borrow_mut() += 1;
-            Poll::Ready(())
+            if let Some(waker) = root_waker.borrow_mut().take() {
+                waker.wake();
+            }
+            if *cell2.borrow() < 10 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
         }));
         let result = block_on(f);
         assert_eq!(result, 20);
     }
+
+    #[test]
+    fn join_random_schedules() {
+        sweep(1000, |seed| {
+            let done = Cell::new(0);
+            let root_waker = RefCell::new(None::<Waker>);
+            let finish = || {
+                done.set(done.get() + 1);
+                if let Some(waker) = root_waker.borrow_mut().take() {
+                    waker.wake();
+                }
+            };
+            let ex = RandomExecutor::new(seed);
+
+            let f = join(ex.interrupt(poll_fn(|cx| {
+                if done.get() < 2 {
+                    *root_waker.borrow_mut() = Some(cx.waker().clone());
+                    Poll::Pending
+                } else {
+                    Poll::Ready(done.get())
+                }
+            })))
+            .with(ex.interrupt(async {
+                ex.spawn_local(async {}).await;
+                finish();
+            }))
+            .with(ex.interrupt(async {
+                ex.spawn_local(async {}).await;
+                finish();
+            }));
+            assert_eq!(ex.run_until(f), 2);
+        });
+    }
+
+    #[test]
+    fn finished_side_is_not_polled_again() {
+        let mut n = 0;
+        let side = Instrumented::new(async {});
+        let stats = side.stats();
+        let f = join(poll_fn(|cx| {
+            n += 1;
+            if n < 5 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(n)
+            }
+        }))
+        .with(side);
+        assert_eq!(block_on(f), 5);
+        assert_eq!(stats.polls(), 1);
+    }
+
+    /// A side task that needs `polls` polls to finish, then sets `done`.
+    fn slow_side(polls: usize, done: &Cell<bool>) -> impl Future<Output = ()> + '_ {
+        let mut remaining = polls;
+        poll_fn(move |cx| {
+            remaining -= 1;
+            if remaining == 0 {
+                done.set(true);
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+    }
+
+    #[test]
+    fn root_cancels_sides() {
+        let done = Cell::new(false);
+        assert_eq!(block_on(join(async { 1 }).with(slow_side(3, &done))), 1);
+        assert!(!done.get());
+    }
+
+    #[test]
+    fn wait_all() {
+        let done1 = Cell::new(false);
+        let done2 = Cell::new(false);
+        let f = join(async { 1 })
+            .with(slow_side(3, &done1))
+            .with(slow_side(5, &done2))
+            .wait_all();
+        assert_eq!(block_on(f), 1);
+        assert!(done1.get());
+        assert!(done2.get());
+    }
+
+    #[test]
+    fn keep_remaining() {
+        let root_done = Cell::new(false);
+        let done1 = Cell::new(false);
+        let done2 = Cell::new(false);
+        let f = join(slow_side(3, &root_done))
+            .with(slow_side(1, &done1))
+            .with(slow_side(5, &done2))
+            .keep_remaining();
+        let ((), remaining) = block_on(f);
+        assert!(root_done.get());
+        assert!(done1.get());
+        assert!(!done2.get());
+        assert_eq!(remaining.len(), 1);
+
+        block_on(remaining);
+        assert!(done2.get());
+    }
+
+    #[test]
+    fn drop_remaining() {
+        let done = Cell::new(false);
+        let (output, remaining) =
+            block_on(join(async { 1 }).with(slow_side(5, &done)).keep_remaining());
+        assert_eq!(output, 1);
+        drop(remaining);
+        assert!(!done.get());
+    }
+
+    #[test]
+    fn try_with_all_ok() {
+        let done = Cell::new(false);
+        let f = join(async { Ok::<_, &str>(1) })
+            .try_with(async { Ok(()) })
+            .with(slow_side(3, &done))
+            .wait_all();
+        assert_eq!(block_on(f), Ok(1));
+        assert!(done.get());
+    }
+
+    #[test]
+    fn try_with_short_circuits() {
+        struct Guard<'a>(&'static str, &'a RefCell<Vec<&'static str>>);
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                self.1.borrow_mut().push(self.0);
+            }
+        }
+
+        let clock = Clock::new();
+        let start = clock.now();
+        let dropped = RefCell::new(vec![]);
+        let sleep_guarded = |name, secs| {
+            let (clock, dropped) = (&clock, &dropped);
+            async move {
+                let _guard = Guard(name, dropped);
+                clock.sleep(Duration::from_secs(secs)).await;
+            }
+        };
+        let f = join(async {
+            sleep_guarded("root", 10).await;
+            Ok(1)
+        })
+        .try_with(async {
+            sleep_guarded("a", 10).await;
+            Ok(())
+        })
+        .try_with(async {
+            clock.sleep(Duration::from_secs(1)).await;
+            Err("failed")
+        })
+        .with(sleep_guarded("c", 10))
+        .wait_all();
+
+        assert_eq!(clock.block_on(f), Err("failed"));
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+        // Newest branch first, root last.
+        assert_eq!(*dropped.borrow(), vec!["c", "a", "root"]);
+    }
+
+    #[test]
+    fn root_error_short_circuits() {
+        let done = Cell::new(false);
+        let f = join(async { Err::<i32, _>("root") })
+            .try_with(slow_side_ok(5, &done))
+            .wait_all();
+        assert_eq!(block_on(f), Err("root"));
+        assert!(!done.get());
+    }
+
+    async fn slow_side_ok<E>(polls: usize, done: &Cell<bool>) -> Result<(), E> {
+        slow_side(polls, done).await;
+        Ok(())
+    }
+
+    #[test]
+    fn side_panic_keeps_root_running() {
+        let done = Cell::new(false);
+        let f = join(async {
+            slow_side(5, &done).await;
+            1
+        })
+        .with(async {
+            if !done.get() {
+                panic!("side");
+            }
+        })
+        .catch_unwind();
+        let caught = block_on(f).unwrap_err();
+        assert!(done.get());
+        assert_eq!(caught.branches().collect::<Vec<_>>(), vec![1]);
+        let payloads = caught.into_payloads();
+        assert_eq!(payloads[0].1.downcast_ref::<&str>(), Some(&"side"));
+    }
+
+    #[test]
+    fn root_panic_waits_for_sides() {
+        let done = Cell::new(false);
+        let f = join(async {
+            if !done.get() {
+                panic!("root");
+            }
+        })
+        .with(slow_side(5, &done))
+        .wait_all()
+        .catch_unwind();
+        let caught = block_on(f).unwrap_err();
+        assert!(caught.root_panicked());
+        assert!(done.get());
+    }
+
+    #[test]
+    #[should_panic = "root"]
+    fn resume_caught_panic() {
+        let f = join(async {
+            if true {
+                panic!("root");
+            }
+        })
+        .catch_unwind();
+        block_on(f).unwrap_err().resume();
+    }
+
+    #[test]
+    fn catch_unwind_without_panics() {
+        let f = join(async { 1 }).with(async {}).wait_all().catch_unwind();
+        assert_eq!(block_on(f).unwrap(), 1);
+    }
+
+    #[test]
+    fn shutdown_lets_sides_clean_up() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let token = CancellationToken::new();
+        let cleaned_up = Cell::new(false);
+        let dropped = Cell::new(false);
+        let f = join(clock.sleep(Duration::from_secs(1)))
+            .with(async {
+                token.cancelled().await;
+                clock.sleep(Duration::from_secs(1)).await;
+                cleaned_up.set(true);
+            })
+            .with(async {
+                // Ignores the token, so it gets dropped after the grace period.
+                let _guard = SetOnDrop(&dropped);
+                clock.sleep(Duration::from_secs(100)).await;
+            })
+            .shutdown_with(token.clone(), async {
+                clock.sleep(Duration::from_secs(5)).await;
+            });
+        clock.block_on(f.into_future());
+        assert!(token.is_cancelled());
+        assert!(cleaned_up.get());
+        assert!(dropped.get());
+        assert_eq!(clock.now() - start, Duration::from_secs(6));
+    }
+
+    #[test]
+    fn shutdown_ends_early_when_sides_finish() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let token = CancellationToken::new();
+        let f = join(async { 1 })
+            .with(token.cancelled())
+            .shutdown_with(token.clone(), async {
+                clock.sleep(Duration::from_secs(5)).await;
+            });
+        assert_eq!(clock.block_on(f.into_future()), 1);
+        assert_eq!(clock.now(), start);
+    }
+
+    struct SetOnDrop<'a>(&'a Cell<bool>);
+
+    impl Drop for SetOnDrop<'_> {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[test]
+    fn finished_side_does_not_spin() {
+        let ex = LocalExecutor::new();
+        let f = Instrumented::new(
+            join(ex.spawn_local(async { 1 }))
+                .with(async {})
+                .with(async {})
+                .into_future(),
+        );
+        let stats = f.stats();
+        assert_eq!(ex.run_until(f), 1);
+        // Once when the sides finish and once when the spawned task wakes the
+        // root.
+        assert_eq!(stats.polls(), 2);
+        assert_eq!(stats.unwoken_polls(), 0);
+        assert_eq!(stats.wakes(), 1);
+    }
+
+    #[test]
+    fn rotation_skips_finished_branches() {
+        let pending = || poll_fn(|_cx| Poll::<()>::Pending);
+        let mut f = pin!(join(pending())
+            .with(pending())
+            .with(async {})
+            .with(pending()));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // Finish the `async {}` branch, which is at depth 1.
+        assert!(f.as_mut().poll_depth(&mut cx, 1).is_ready());
+
+        let mut start = f.size();
+        let starts: Vec<_> = (0..6)
+            .map(|_| next_start(f.size(), |depth| f.is_done(depth), &mut start))
+            .collect();
+        assert_eq!(starts, vec![3, 2, 0, 3, 2, 0]);
+    }
+
+    #[test]
+    fn only_woken_branches_are_polled() {
+        let busy_done = Cell::new(false);
+        let idle = Instrumented::new(poll_fn(|_cx| Poll::<()>::Pending));
+        let idle_stats = idle.stats();
+        let root = Instrumented::new(poll_fn(|cx| {
+            if busy_done.get() {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }));
+        let root_stats = root.stats();
+
+        block_on(join(root).with(idle).with(slow_side(10, &busy_done)));
+
+        assert_eq!(idle_stats.polls(), 1);
+        assert_eq!(root_stats.unwoken_polls(), 0);
+    }
+
+    #[test]
+    fn many_sides() {
+        let count = Cell::new(0);
+        let root_waker = RefCell::new(None::<Waker>);
+        let side = || async {
+            // Wait for one wakeup before finishing.
+            let mut first = true;
+            poll_fn(|cx| {
+                if std::mem::take(&mut first) {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            })
+            .await;
+            count.set(count.get() + 1);
+            if let Some(waker) = root_waker.borrow_mut().take() {
+                waker.wake();
+            }
+        };
+        let root = poll_fn(|cx| {
+            if count.get() == 66 {
+                Poll::Ready(count.get())
+            } else {
+                *root_waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        });
+
+        // Enough sides to need more than one word of ready bits.
+        macro_rules! with8 {
+            ($j:expr) => {
+                $j.with(side())
+                    .with(side())
+                    .with(side())
+                    .with(side())
+                    .with(side())
+                    .with(side())
+                    .with(side())
+                    .with(side())
+            };
+        }
+        let f = with8!(with8!(with8!(with8!(with8!(with8!(with8!(with8!(
+            join(root)
+        ))))))))
+        .with(side())
+        .with(side());
+        assert_eq!(f.size(), 67);
+
+        let ex = LocalExecutor::new();
+        let result = ex.run_until(f);
+        assert_eq!(result, 66);
+    }
 }
@@ -0,0 +1,249 @@
+//! Joins a number of futures that is only known at runtime.
+//!
+//! The futures live in a pinned boxed slice and each one gets its own waker
+//! from a [`ReadySet`], so a poll only touches the futures that were woken.
+
+use std::{
+    async_iter::AsyncIterator,
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::ready::ReadySet;
+
+enum MaybeDone<F: Future> {
+    Pending(F),
+    Done(F::Output),
+    Taken,
+}
+
+/// Runs every future from `iter` concurrently and returns their outputs in the
+/// same order as the input.
+pub fn join_all<I>(iter: I) -> JoinAll<<I::Item as IntoFuture>::IntoFuture>
+where
+    I: IntoIterator,
+    I::Item: IntoFuture,
+{
+    let futures: Box<[_]> = iter
+        .into_iter()
+        .map(|f| MaybeDone::Pending(f.into_future()))
+        .collect();
+    JoinAll {
+        ready: ReadySet::new(futures.len()),
+        remaining: futures.len(),
+        futures: Box::into_pin(futures),
+        completed: false,
+    }
+}
+
+pub struct JoinAll<F: Future> {
+    futures: Pin<Box<[MaybeDone<F>]>>,
+    ready: ReadySet,
+    remaining: usize,
+    /// Set once the outputs have been returned. With no futures there are no
+    /// `Taken` slots to notice a second poll, so this catches it instead.
+    completed: bool,
+}
+
+// The futures are pinned on the heap, so moving the handle is fine.
+impl<F: Future> Unpin for JoinAll<F> {}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        assert!(!this.completed, "JoinAll polled after completion");
+        this.ready.register(cx.waker());
+        this.ready.take();
+        // SAFETY: we only move outputs out of the slice, never the futures.
+        let futures = unsafe { this.futures.as_mut().get_unchecked_mut() };
+        for i in this.ready.woken_from(0) {
+            if let MaybeDone::Pending(f) = &mut futures[i] {
+                let mut cx = Context::from_waker(this.ready.waker(i));
+                if let Poll::Ready(output) = unsafe { Pin::new_unchecked(f) }.poll(&mut cx) {
+                    futures[i] = MaybeDone::Done(output);
+                    this.remaining -= 1;
+                }
+            }
+        }
+
+        if this.remaining > 0 {
+            return Poll::Pending;
+        }
+        this.completed = true;
+        Poll::Ready(
+            futures
+                .iter_mut()
+                .map(|f| match std::mem::replace(f, MaybeDone::Taken) {
+                    MaybeDone::Done(output) => output,
+                    _ => unreachable!("every future has finished"),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Runs every future from `iter` concurrently, yielding each output as soon as
+/// its future finishes.
+pub fn join_all_unordered<I>(iter: I) -> JoinAllUnordered<<I::Item as IntoFuture>::IntoFuture>
+where
+    I: IntoIterator,
+    I::Item: IntoFuture,
+{
+    let futures: Box<[_]> = iter.into_iter().map(|f| Some(f.into_future())).collect();
+    JoinAllUnordered {
+        ready: ReadySet::new(futures.len()),
+        remaining: futures.len(),
+        futures: Box::into_pin(futures),
+        finished: VecDeque::new(),
+    }
+}
+
+pub struct JoinAllUnordered<F: Future> {
+    futures: Pin<Box<[Option<F>]>>,
+    ready: ReadySet,
+    remaining: usize,
+    /// Outputs of futures that finished during the same poll as another one,
+    /// waiting to be yielded.
+    finished: VecDeque<F::Output>,
+}
+
+impl<F: Future> Unpin for JoinAllUnordered<F> {}
+
+impl<F: Future> AsyncIterator for JoinAllUnordered<F> {
+    type Item = F::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(output) = this.finished.pop_front() {
+            return Poll::Ready(Some(output));
+        }
+        if this.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        this.ready.register(cx.waker());
+        this.ready.take();
+        // SAFETY: finished futures are dropped in place, never moved.
+        let futures = unsafe { this.futures.as_mut().get_unchecked_mut() };
+        for i in this.ready.woken_from(0) {
+            if let Some(f) = &mut futures[i] {
+                let mut cx = Context::from_waker(this.ready.waker(i));
+                if let Poll::Ready(output) = unsafe { Pin::new_unchecked(f) }.poll(&mut cx) {
+                    futures[i] = None;
+                    this.remaining -= 1;
+                    this.finished.push_back(output);
+                }
+            }
+        }
+
+        match this.finished.pop_front() {
+            Some(output) => Poll::Ready(Some(output)),
+            None => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.remaining + self.finished.len();
+        (len, Some(len))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::{poll_fn, Future},
+        pin::Pin,
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
+
+    use super::{join_all, join_all_unordered};
+    use crate::{
+        block_on,
+        instrument::Instrumented,
+        time::Clock,
+        Either::{Left, Right},
+    };
+
+    #[test]
+    fn outputs_in_input_order() {
+        let clock = Clock::new();
+        let futures = (0..5).map(|i| {
+            let clock = &clock;
+            async move {
+                // Later futures finish first.
+                clock.sleep(Duration::from_secs(5 - i)).await;
+                i
+            }
+        });
+        assert_eq!(clock.block_on(join_all(futures)), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn empty() {
+        let futures: Vec<std::future::Ready<i32>> = vec![];
+        assert_eq!(block_on(join_all(futures)), Vec::<i32>::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "JoinAll polled after completion")]
+    fn empty_panics_when_polled_after_completion() {
+        let futures: Vec<std::future::Ready<i32>> = vec![];
+        let mut join = join_all(futures);
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Pin::new(&mut join).poll(&mut cx), Poll::Ready(vec![]));
+        let _ = Pin::new(&mut join).poll(&mut cx);
+    }
+
+    #[test]
+    fn only_woken_futures_are_polled() {
+        let clock = Clock::new();
+        let sleeps: Vec<_> = (0..100)
+            .map(|i| Instrumented::new(clock.sleep(Duration::from_secs(i % 2 + 1))))
+            .collect();
+        let stats: Vec<_> = sleeps.iter().map(Instrumented::stats).collect();
+        let mut busy_polls = 0;
+        let busy = poll_fn(|cx| {
+            busy_polls += 1;
+            if busy_polls < 10 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        });
+
+        let futures = sleeps.into_iter().map(Left).chain([Right(busy)]);
+        clock.block_on(join_all(futures));
+
+        // Each sleep is polled once to register its timer and once when it
+        // fires, no matter how often the busy future wakes itself.
+        for stats in stats {
+            assert_eq!(stats.polls(), 2);
+        }
+    }
+
+    #[test]
+    fn unordered_yields_in_completion_order() {
+        let clock = Clock::new();
+        let futures = [3, 1, 2].map(|secs| {
+            let clock = &clock;
+            async move {
+                clock.sleep(Duration::from_secs(secs)).await;
+                secs
+            }
+        });
+        let result = clock.block_on(async {
+            let mut result = vec![];
+            for await secs in join_all_unordered(futures) {
+                result.push(secs);
+            }
+            result
+        });
+        assert_eq!(result, vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Joins a tuple of futures and returns a tuple of their outputs.
+//!
+//! This is built on the [`join`] chain. Each future becomes one branch that
+//! stores its output in a local slot, and the chain runs with
+//! [`JoinFuture::wait_all`] so that every branch finishes.
+//!
+//! [`TryJoinTuple`] does the same for futures that return a `Result`, using
+//! [`JoinFuture::try_with`] so that the first error ends the join.
+
+use super::join::{join, JoinFuture};
+
+pub trait JoinTuple {
+    type Output;
+
+    /// Runs every future in the tuple concurrently and returns all of their
+    /// outputs once they have all finished.
+    fn join(self) -> impl Future<Output = Self::Output>;
+}
+
+/// Same as [`JoinTuple::join`], for when a free function reads better.
+pub fn join_tuple<T: JoinTuple>(futures: T) -> impl Future<Output = T::Output> {
+    futures.join()
+}
+
+pub trait TryJoinTuple {
+    type Output;
+    type Error;
+
+    /// Runs every future in the tuple concurrently and returns all of their
+    /// `Ok` values once they have all succeeded.
+    ///
+    /// As soon as one of them returns `Err`, resolves to that error. The
+    /// futures that are still running are dropped from last to first.
+    fn try_join(self) -> impl Future<Output = Result<Self::Output, Self::Error>>;
+}
+
+/// Same as [`TryJoinTuple::try_join`], for when a free function reads better.
+pub fn try_join_tuple<T: TryJoinTuple>(
+    futures: T,
+) -> impl Future<Output = Result<T::Output, T::Error>> {
+    futures.try_join()
+}
+
+macro_rules! impl_join_tuple {
+    ($T0:ident $f0:ident $o0:ident $(, $T:ident $f:ident $o:ident)*) => {
+        impl<$T0: IntoFuture, $($T: IntoFuture),*> JoinTuple for ($T0, $($T,)*) {
+            type Output = ($T0::Output, $($T::Output,)*);
+
+            async fn join(self) -> Self::Output {
+                let ($f0, $($f,)*) = self;
+                let mut $o0 = None;
+                $(let mut $o = None;)*
+                join(async { $o0 = Some($f0.await) })
+                    $(.with(async { $o = Some($f.await) }))*
+                    .wait_all()
+                    .await;
+                ($o0.unwrap(), $($o.unwrap(),)*)
+            }
+        }
+    };
+}
+
+macro_rules! impl_try_join_tuple {
+    ($T0:ident $f0:ident $o0:ident $(, $T:ident $f:ident $o:ident)*) => {
+        impl<E, $T0, $($T),*> TryJoinTuple for ($T0, $($T,)*)
+        where
+            $T0: IntoFuture<Output: IsResult<Err = E>>,
+            $($T: IntoFuture<Output: IsResult<Err = E>>,)*
+        {
+            type Output = (
+                <$T0::Output as IsResult>::Ok,
+                $(<$T::Output as IsResult>::Ok,)*
+            );
+            type Error = E;
+
+            async fn try_join(self) -> Result<Self::Output, E> {
+                let ($f0, $($f,)*) = self;
+                let mut $o0 = None;
+                $(let mut $o = None;)*
+                join(async {
+                    $o0 = Some($f0.await.into_result()?);
+                    Ok::<(), E>(())
+                })
+                $(.try_with(async {
+                    $o = Some($f.await.into_result()?);
+                    Ok(())
+                }))*
+                .wait_all()
+                .await?;
+                Ok(($o0.unwrap(), $($o.unwrap(),)*))
+            }
+        }
+    };
+}
+
+use sealed::IsResult;
+
+mod sealed {
+    /// Lets the tuple impls name the `Ok` and `Err` types of each future's
+    /// output. It lives in a private module so it is not part of the API.
+    pub trait IsResult {
+        type Ok;
+        type Err;
+
+        fn into_result(self) -> Result<Self::Ok, Self::Err>;
+    }
+
+    impl<T, E> IsResult for Result<T, E> {
+        type Ok = T;
+        type Err = E;
+
+        fn into_result(self) -> Self {
+            self
+        }
+    }
+}
+
+/// Implements `JoinTuple` and `TryJoinTuple` for the given tuple and every
+/// suffix of it.
+macro_rules! impl_join_tuples {
+    ($T0:ident $f0:ident $o0:ident $(, $T:ident $f:ident $o:ident)*) => {
+        impl_join_tuple!($T0 $f0 $o0 $(, $T $f $o)*);
+        impl_try_join_tuple!($T0 $f0 $o0 $(, $T $f $o)*);
+        impl_join_tuples!($($T $f $o),*);
+    };
+    () => {};
+}
+
+impl_join_tuples!(
+    T0 f0 o0,
+    T1 f1 o1,
+    T2 f2 o2,
+    T3 f3 o3,
+    T4 f4 o4,
+    T5 f5 o5,
+    T6 f6 o6,
+    T7 f7 o7,
+    T8 f8 o8,
+    T9 f9 o9,
+    T10 f10 o10,
+    T11 f11 o11
+);
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{join_tuple, try_join_tuple, JoinTuple, TryJoinTuple};
+    use crate::{block_on, executor::LocalExecutor, time::Clock};
+
+    #[test]
+    fn mixed_outputs() {
+        let result = block_on((async { 1 }, async { "two" }, async { 3.0 }).join());
+        assert_eq!(result, (1, "two", 3.0));
+    }
+
+    #[test]
+    fn single() {
+        assert_eq!(block_on(join_tuple((async { 1 },))), (1,));
+    }
+
+    #[test]
+    fn twelve() {
+        let result = block_on(join_tuple((
+            async { 0 },
+            async { 1 },
+            async { 2 },
+            async { 3 },
+            async { 4 },
+            async { 5 },
+            async { 6 },
+            async { 7 },
+            async { 8 },
+            async { 9 },
+            async { 10 },
+            async { 11u8 },
+        )));
+        assert_eq!(result, (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11u8));
+    }
+
+    #[test]
+    fn waits_for_slowest() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let sleep_then = |secs, value| {
+            let clock = &clock;
+            async move {
+                clock.sleep(Duration::from_secs(secs)).await;
+                value
+            }
+        };
+        let result =
+            clock.block_on((sleep_then(3, 'a'), sleep_then(1, 'b'), sleep_then(2, 'c')).join());
+        assert_eq!(result, ('a', 'b', 'c'));
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn try_join_all_ok() {
+        let result = block_on((async { Ok::<_, ()>(1) }, async { Ok("two") }).try_join());
+        assert_eq!(result, Ok((1, "two")));
+    }
+
+    #[test]
+    fn try_join_first_error() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let sleep_then = |secs, result: Result<u64, u64>| {
+            let clock = &clock;
+            async move {
+                clock.sleep(Duration::from_secs(secs)).await;
+                result
+            }
+        };
+        let result = clock.block_on(try_join_tuple((
+            sleep_then(3, Ok(3)),
+            sleep_then(2, Err(2)),
+            sleep_then(1, Err(1)),
+        )));
+        assert_eq!(result, Err(1));
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn spawned_tasks() {
+        let ex = LocalExecutor::new();
+        let a = ex.spawn_local(async { 1 });
+        let b = ex.spawn_local(async { String::from("b") });
+        assert_eq!(ex.run_until((a, b).join()), (1, String::from("b")));
+    }
+}
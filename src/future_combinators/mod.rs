@@ -1,44 +1,28 @@
 //! Common combinators on futures that we use to build async iterator
 //! combinators.
 
-use std::{future::poll_fn, pin::pin, task::Poll};
-
-use crate::Either;
-
+mod cancel;
+mod fairness;
 mod join;
+mod join_all;
+mod join_tuple;
+mod race;
+mod ready;
+mod scope;
+mod timeout;
 
-pub use join::{join, JoinFuture, JoinRoot, JoinWith};
-
-pub async fn race<A, B>(a: A, b: B) -> Either<A::Output, B::Output>
-where
-    A: IntoFuture,
-    B: IntoFuture,
-{
-    let mut a = pin!(a.into_future());
-    let mut b = pin!(b.into_future());
-    let mut parity = false;
-    poll_fn(|cx| {
-        parity = !parity;
-
-        if parity {
-            // poll a then b
-            match a.as_mut().poll(cx) {
-                Poll::Ready(item) => Poll::Ready(Either::Left(item)),
-                Poll::Pending => match b.as_mut().poll(cx) {
-                    Poll::Ready(item) => Poll::Ready(Either::Right(item)),
-                    Poll::Pending => Poll::Pending,
-                },
-            }
-        } else {
-            // poll b then a
-            match b.as_mut().poll(cx) {
-                Poll::Ready(item) => Poll::Ready(Either::Right(item)),
-                Poll::Pending => match a.as_mut().poll(cx) {
-                    Poll::Ready(item) => Poll::Ready(Either::Left(item)),
-                    Poll::Pending => Poll::Pending,
-                },
-            }
-        }
-    })
-    .await
-}
+pub use cancel::{CancellationToken, WaitForCancellation};
+pub use fairness::Fairness;
+pub(crate) use fairness::Turns;
+pub use join::{
+    join, CatchUnwind, JoinFuture, JoinPanic, JoinRoot, JoinWith, KeepRemaining, Remaining,
+    Shutdown, TryWith, WaitAll,
+};
+pub use join_all::{join_all, join_all_unordered, JoinAll, JoinAllUnordered};
+pub use join_tuple::{join_tuple, try_join_tuple, JoinTuple, TryJoinTuple};
+pub use race::{
+    race, race_all, race_keep, race_shutdown, race_tuple, race_with, OneOf10, OneOf11, OneOf12,
+    OneOf2, OneOf3, OneOf4, OneOf5, OneOf6, OneOf7, OneOf8, OneOf9, RaceTuple,
+};
+pub use scope::{scope, Cancelled, Scope};
+pub use timeout::{deadline, timeout, Elapsed, Timeout};
@@ -0,0 +1,440 @@
+//! Runs futures concurrently and returns the output of whichever finishes
+//! first, dropping the rest.
+//!
+//! Every race polls its branches in a rotating order: each poll starts one
+//! branch further along than the last, so no branch can starve the others just
+//! by being earlier in the list. Two-way races can pick a different
+//! [`Fairness`] strategy with [`race_with`].
+//!
+//! [`race_keep`] is the exception to dropping the losers: it hands the losing
+//! future back so its progress isn't lost. [`race_shutdown`] gives the loser a
+//! chance to clean up before it is dropped.
+
+use std::{
+    future::poll_fn,
+    pin::{pin, Pin},
+    task::Poll,
+};
+
+use super::{CancellationToken, Fairness, Turns};
+use crate::Either::{self, Left, Right};
+
+/// The order to poll `len` branches in, moving the first branch along by one
+/// on every poll. For two branches this is [`Fairness::RoundRobin`].
+struct Rotation {
+    start: usize,
+    len: usize,
+}
+
+impl Rotation {
+    fn new(len: usize) -> Self {
+        Rotation { start: 0, len }
+    }
+
+    fn next(&mut self) -> impl Iterator<Item = usize> + use<> {
+        let start = self.start;
+        self.start = (start + 1) % self.len;
+        (start..self.len).chain(0..start)
+    }
+}
+
+pub async fn race<A, B>(a: A, b: B) -> Either<A::Output, B::Output>
+where
+    A: IntoFuture,
+    B: IntoFuture,
+{
+    race_with(a, b, Fairness::RoundRobin).await
+}
+
+/// Like [`race`], but `fairness` decides which future is polled first each
+/// time.
+pub async fn race_with<A, B>(a: A, b: B, fairness: Fairness) -> Either<A::Output, B::Output>
+where
+    A: IntoFuture,
+    B: IntoFuture,
+{
+    let mut a = pin!(a.into_future());
+    let mut b = pin!(b.into_future());
+    let mut turns = Turns::new(fairness);
+    poll_fn(|cx| {
+        let left_first = turns.left_first();
+        for left in [left_first, !left_first] {
+            let poll = if left {
+                a.as_mut().poll(cx).map(Left)
+            } else {
+                b.as_mut().poll(cx).map(Right)
+            };
+            if poll.is_ready() {
+                return poll;
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Races two futures like [`race`], but hands back the one that lost instead
+/// of dropping it, so the caller can resume it later.
+///
+/// The futures must be `Unpin`. To race futures that aren't, pin them first
+/// and pass `Pin<&mut F>`, which gives the pinned reference back.
+pub async fn race_keep<A, B>(a: A, b: B) -> Either<(A::Output, B), (A, B::Output)>
+where
+    A: Future + Unpin,
+    B: Future + Unpin,
+{
+    let mut sides = Some((a, b));
+    let mut order = Rotation::new(2);
+    poll_fn(move |cx| {
+        let (a, b) = sides.as_mut().expect("race_keep polled after completion");
+        for i in order.next() {
+            if i == 0 {
+                if let Poll::Ready(output) = Pin::new(&mut *a).poll(cx) {
+                    let (_, b) = sides.take().unwrap();
+                    return Poll::Ready(Left((output, b)));
+                }
+            } else if let Poll::Ready(output) = Pin::new(&mut *b).poll(cx) {
+                let (a, _) = sides.take().unwrap();
+                return Poll::Ready(Right((a, output)));
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Races two futures like [`race`], then cancels `token` and keeps polling the
+/// loser until it finishes or `grace` completes, so it can shut down cleanly.
+/// The loser's output is discarded.
+///
+/// `grace` isn't polled until there is a winner.
+pub async fn race_shutdown<A, B, G>(
+    a: A,
+    b: B,
+    token: &CancellationToken,
+    grace: G,
+) -> Either<A::Output, B::Output>
+where
+    A: IntoFuture,
+    B: IntoFuture,
+    G: IntoFuture<Output = ()>,
+{
+    let a = pin!(a.into_future());
+    let b = pin!(b.into_future());
+    let winner = race_keep(a, b).await;
+    token.cancel();
+    match winner {
+        Left((output, loser)) => {
+            race(loser, grace).await;
+            Left(output)
+        }
+        Right((loser, output)) => {
+            race(loser, grace).await;
+            Right(output)
+        }
+    }
+}
+
+/// Races every future from `iter`, returning the index and output of the first
+/// one to finish.
+///
+/// Panics if `iter` is empty, since there would be nothing to wait for.
+pub async fn race_all<I>(iter: I) -> (usize, <I::Item as IntoFuture>::Output)
+where
+    I: IntoIterator,
+    I::Item: IntoFuture,
+{
+    let futures: Box<[_]> = iter.into_iter().map(IntoFuture::into_future).collect();
+    assert!(!futures.is_empty(), "race_all needs at least one future");
+    let mut futures = Box::into_pin(futures);
+    let mut order = Rotation::new(futures.len());
+    poll_fn(|cx| {
+        for i in order.next() {
+            // SAFETY: the futures are never moved out of the slice.
+            let f = unsafe { futures.as_mut().map_unchecked_mut(|fs| &mut fs[i]) };
+            if let Poll::Ready(output) = f.poll(cx) {
+                return Poll::Ready((i, output));
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+pub trait RaceTuple {
+    type Output;
+
+    /// Runs every future in the tuple concurrently and returns the output of
+    /// the first one to finish, tagged with its position.
+    fn race(self) -> impl Future<Output = Self::Output>;
+}
+
+/// Same as [`RaceTuple::race`], for when a free function reads better.
+pub fn race_tuple<T: RaceTuple>(futures: T) -> impl Future<Output = T::Output> {
+    futures.race()
+}
+
+/// Defines the `OneOfN` enum for one tuple size and implements `RaceTuple` for
+/// that size.
+macro_rules! impl_race_tuple {
+    ($OneOf:ident; $($T:ident $f:ident $i:literal),*) => {
+        /// The output of a [`RaceTuple`] race. The variant says which
+        /// position in the tuple won.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub enum $OneOf<$($T),*> {
+            $($T($T)),*
+        }
+
+        impl<$($T: IntoFuture),*> RaceTuple for ($($T,)*) {
+            type Output = $OneOf<$($T::Output),*>;
+
+            async fn race(self) -> Self::Output {
+                let ($($f,)*) = self;
+                $(let mut $f = pin!($f.into_future());)*
+                let mut order = Rotation::new([$($i),*].len());
+                poll_fn(|cx| {
+                    for i in order.next() {
+                        let poll = match i {
+                            $($i => $f.as_mut().poll(cx).map($OneOf::$T),)*
+                            _ => unreachable!(),
+                        };
+                        if poll.is_ready() {
+                            return poll;
+                        }
+                    }
+                    Poll::Pending
+                })
+                .await
+            }
+        }
+    };
+}
+
+impl_race_tuple!(OneOf2; A fa 0, B fb 1);
+impl_race_tuple!(OneOf3; A fa 0, B fb 1, C fc 2);
+impl_race_tuple!(OneOf4; A fa 0, B fb 1, C fc 2, D fd 3);
+impl_race_tuple!(OneOf5; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4);
+impl_race_tuple!(OneOf6; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4, F ff 5);
+impl_race_tuple!(OneOf7; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4, F ff 5, G fg 6);
+impl_race_tuple!(OneOf8; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4, F ff 5, G fg 6, H fh 7);
+impl_race_tuple!(OneOf9; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4, F ff 5, G fg 6, H fh 7, I fi 8);
+impl_race_tuple!(
+    OneOf10; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4, F ff 5, G fg 6, H fh 7, I fi 8, J fj 9
+);
+impl_race_tuple!(
+    OneOf11; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4, F ff 5, G fg 6, H fh 7, I fi 8, J fj 9,
+    K fk 10
+);
+impl_race_tuple!(
+    OneOf12; A fa 0, B fb 1, C fc 2, D fd 3, E fe 4, F ff 5, G fg 6, H fh 7, I fi 8, J fj 9,
+    K fk 10, L fl 11
+);
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, future::poll_fn, pin::pin, task::Poll, time::Duration};
+
+    use crate::{
+        block_on,
+        executor::{random::sweep, RandomExecutor},
+        instrument::Instrumented,
+        time::Clock,
+        Either::{Left, Right},
+    };
+
+    use super::{
+        race, race_all, race_keep, race_shutdown, race_tuple, race_with, Fairness, OneOf3, OneOf4,
+        RaceTuple,
+    };
+    use crate::future_combinators::CancellationToken;
+
+    #[test]
+    fn race_random_schedules() {
+        let mut left_won = false;
+        let mut right_won = false;
+        sweep(1000, |seed| {
+            let ex = RandomExecutor::new(seed);
+            let a = ex.spawn_local(async { 1 });
+            let b = ex.spawn_local(async { 2 });
+            match ex.run_until(race(ex.interrupt(a), ex.interrupt(b))) {
+                Left(1) => left_won = true,
+                Right(2) => right_won = true,
+                _ => panic!("race returned the wrong output"),
+            }
+        });
+        assert!(left_won && right_won);
+    }
+
+    #[test]
+    fn race_polls_loser_every_tick() {
+        let mut n = 0;
+        let winner = Instrumented::new(poll_fn(|cx| {
+            n += 1;
+            if n < 5 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }));
+        let loser = Instrumented::new(poll_fn(|_cx| Poll::<()>::Pending));
+        let (winner_stats, loser_stats) = (winner.stats(), loser.stats());
+
+        assert!(block_on(race(winner, loser)).is_left());
+        assert_eq!(winner_stats.polls(), 5);
+        assert_eq!(winner_stats.unwoken_polls(), 0);
+        // Both sides share a waker, so the loser is polled whenever the winner
+        // is woken, but never more often than that.
+        assert!(loser_stats.polls() <= winner_stats.polls());
+        assert_eq!(loser_stats.wakes(), 0);
+    }
+
+    #[test]
+    fn race_with_fairness() {
+        let both_ready = |fairness| block_on(race_with(async { 1 }, async { 2 }, fairness));
+        assert_eq!(both_ready(Fairness::Biased), Left(1));
+        assert_eq!(both_ready(Fairness::RoundRobin), Left(1));
+        assert_eq!(both_ready(Fairness::Weighted(0, 1)), Right(2));
+
+        let mut left_won = false;
+        let mut right_won = false;
+        for seed in 0..20 {
+            match both_ready(Fairness::Random(seed)) {
+                Left(_) => left_won = true,
+                Right(_) => right_won = true,
+            }
+        }
+        assert!(left_won && right_won);
+    }
+
+    #[test]
+    fn race_keep_resumes_loser() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let polls = RefCell::new(0);
+        let slow = Box::pin(async {
+            *polls.borrow_mut() += 1;
+            clock.sleep(Duration::from_secs(2)).await;
+            *polls.borrow_mut() += 1;
+            "slow"
+        });
+        let fast = clock.sleep(Duration::from_secs(1));
+
+        let slow = match clock.block_on(race_keep(slow, fast)) {
+            Right((slow, ())) => slow,
+            Left(_) => panic!("the slow side won"),
+        };
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+        assert_eq!(*polls.borrow(), 1);
+
+        // The sleep it started is still running, so it only needs one more
+        // second.
+        assert_eq!(clock.block_on(slow), "slow");
+        assert_eq!(clock.now() - start, Duration::from_secs(2));
+        assert_eq!(*polls.borrow(), 2);
+    }
+
+    #[test]
+    fn race_keep_pinned() {
+        let clock = Clock::new();
+        let mut long = pin!(clock.sleep(Duration::from_secs(5)));
+        let mut short_wins = 0;
+        // Keep racing the same long sleep against a series of short ones.
+        loop {
+            let short = clock.sleep(Duration::from_secs(2));
+            match clock.block_on(race_keep(long.as_mut(), short)) {
+                Left(((), _)) => break,
+                Right((_, ())) => short_wins += 1,
+            }
+        }
+        assert_eq!(short_wins, 2);
+    }
+
+    #[test]
+    fn race_shutdown_lets_loser_clean_up() {
+        let clock = Clock::new();
+        let token = CancellationToken::new();
+        let cleaned_up = RefCell::new(false);
+        let loser = async {
+            token.cancelled().await;
+            clock.sleep(Duration::from_secs(1)).await;
+            *cleaned_up.borrow_mut() = true;
+        };
+        let grace = async { clock.sleep(Duration::from_secs(5)).await };
+        let result = clock.block_on(race_shutdown(async { 1 }, loser, &token, grace));
+        assert_eq!(result, Left(1));
+        assert!(*cleaned_up.borrow());
+    }
+
+    /// A future that logs `id` every time it is polled and finishes once the
+    /// log has `polls` entries.
+    fn logged(id: usize, polls: usize, log: &RefCell<Vec<usize>>) -> impl Future<Output = usize> {
+        poll_fn(move |cx| {
+            log.borrow_mut().push(id);
+            if log.borrow().len() >= polls {
+                Poll::Ready(id)
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+    }
+
+    #[test]
+    fn race_all_first_to_finish() {
+        let clock = Clock::new();
+        let futures = [3, 1, 2].map(|secs| clock.sleep(Duration::from_secs(secs)));
+        assert_eq!(clock.block_on(race_all(futures)).0, 1);
+    }
+
+    #[test]
+    fn race_all_rotates_first_branch() {
+        let log = RefCell::new(vec![]);
+        let futures: Vec<_> = (0..3).map(|id| logged(id, 9, &log)).collect();
+        assert_eq!(block_on(race_all(futures)), (1, 1));
+        assert_eq!(*log.borrow(), vec![0, 1, 2, 1, 2, 0, 2, 0, 1]);
+    }
+
+    #[test]
+    #[should_panic = "race_all needs at least one future"]
+    fn race_all_empty() {
+        block_on(race_all(Vec::<std::future::Ready<()>>::new()));
+    }
+
+    #[test]
+    fn race_tuple_mixed_outputs() {
+        let clock = Clock::new();
+        let sleep = |secs| clock.sleep(Duration::from_secs(secs));
+        let result = clock.block_on(
+            (
+                async {
+                    sleep(3).await;
+                    'a'
+                },
+                async {
+                    sleep(1).await;
+                    "b"
+                },
+                async {
+                    sleep(2).await;
+                    3
+                },
+            )
+                .race(),
+        );
+        assert_eq!(result, OneOf3::B("b"));
+    }
+
+    #[test]
+    fn race_tuple_rotates_first_branch() {
+        let log = RefCell::new(vec![]);
+        let result = block_on(race_tuple((
+            logged(0, 8, &log),
+            async { logged(1, 8, &log).await.to_string() },
+            logged(2, 8, &log),
+            logged(3, 8, &log),
+        )));
+        assert_eq!(result, OneOf4::A(0));
+        assert_eq!(*log.borrow(), vec![0, 1, 2, 3, 1, 2, 3, 0]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Per-branch wakers for combinators that drive several futures at once.
+//!
+//! Each branch gets its own child waker. Waking it sets the branch's bit in a
+//! shared bitset and then wakes the parent, so the parent only has to poll the
+//! branches that were actually woken, much like `FuturesUnordered`.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Wake, Waker},
+};
+
+pub(crate) struct ReadySet {
+    shared: Arc<Shared>,
+    wakers: Vec<Waker>,
+    /// The bits taken by the last call to `take`.
+    snapshot: Vec<u64>,
+}
+
+struct Shared {
+    bits: Vec<AtomicU64>,
+    parent: Mutex<Option<Waker>>,
+}
+
+struct BranchWaker {
+    index: usize,
+    shared: Arc<Shared>,
+}
+
+impl Wake for BranchWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let bit = 1 << (self.index % 64);
+        let old = self.shared.bits[self.index / 64].fetch_or(bit, Ordering::AcqRel);
+        // If the bit was already set, the parent has been woken and hasn't
+        // taken it yet.
+        if old & bit == 0 {
+            let parent = self.shared.parent.lock().unwrap().clone();
+            if let Some(parent) = parent {
+                parent.wake();
+            }
+        }
+    }
+}
+
+impl ReadySet {
+    /// Creates a set for `len` branches, all of which start out ready so they
+    /// get their first poll.
+    pub(crate) fn new(len: usize) -> Self {
+        let words = len.div_ceil(64);
+        let bits = (0..words)
+            .map(|w| {
+                let n = (len - w * 64).min(64);
+                AtomicU64::new(if n == 64 { u64::MAX } else { (1 << n) - 1 })
+            })
+            .collect();
+        let shared = Arc::new(Shared {
+            bits,
+            parent: Mutex::new(None),
+        });
+        let wakers = (0..len)
+            .map(|index| {
+                Waker::from(Arc::new(BranchWaker {
+                    index,
+                    shared: shared.clone(),
+                }))
+            })
+            .collect();
+        ReadySet {
+            shared,
+            wakers,
+            snapshot: vec![0; words],
+        }
+    }
+
+    /// Sets the waker to wake when any branch is woken.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut parent = self.shared.parent.lock().unwrap();
+        match &mut *parent {
+            Some(old) => old.clone_from(waker),
+            None => *parent = Some(waker.clone()),
+        }
+    }
+
+    /// Takes the set of branches woken since the last call.
+    ///
+    /// Wakeups that happen after this, including ones that happen while
+    /// polling the branches we just took, are saved for the next call.
+    pub(crate) fn take(&mut self) {
+        for (word, bits) in self.snapshot.iter_mut().zip(&self.shared.bits) {
+            *word = bits.swap(0, Ordering::AcqRel);
+        }
+    }
+
+    /// Iterates over the branches returned by the last `take`, starting at
+    /// `start` and wrapping around.
+    pub(crate) fn woken_from(&self, start: usize) -> impl Iterator<Item = usize> + '_ {
+        self.woken()
+            .filter(move |&i| i >= start)
+            .chain(self.woken().filter(move |&i| i < start))
+    }
+
+    /// Marks branch `index` as woken without waking the parent.
+    pub(crate) fn mark(&self, index: usize) {
+        self.shared.bits[index / 64].fetch_or(1 << (index % 64), Ordering::AcqRel);
+    }
+
+    /// Returns the child waker for branch `index`.
+    pub(crate) fn waker(&self, index: usize) -> &Waker {
+        &self.wakers[index]
+    }
+
+    fn woken(&self) -> impl Iterator<Item = usize> + '_ {
+        self.snapshot.iter().enumerate().flat_map(|(w, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(w * 64 + bit)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReadySet;
+
+    #[test]
+    fn all_ready_at_first() {
+        let mut ready = ReadySet::new(70);
+        ready.take();
+        assert_eq!(ready.woken_from(0).count(), 70);
+        ready.take();
+        assert_eq!(ready.woken_from(0).count(), 0);
+    }
+
+    #[test]
+    fn wake_sets_bit() {
+        let mut ready = ReadySet::new(130);
+        ready.take();
+        ready.waker(3).wake_by_ref();
+        ready.waker(129).wake_by_ref();
+        ready.waker(64).wake_by_ref();
+        ready.take();
+        assert_eq!(ready.woken_from(0).collect::<Vec<_>>(), vec![3, 64, 129]);
+        assert_eq!(ready.woken_from(64).collect::<Vec<_>>(), vec![64, 129, 3]);
+    }
+}
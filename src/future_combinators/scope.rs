@@ -0,0 +1,372 @@
+//! Structured concurrency for futures that borrow from the caller's stack.
+//!
+//! Futures spawned on a [`Scope`] only ever run inside the future returned by
+//! [`scope`], as one branch of a join next to the scope's body. That is what
+//! lets them borrow locals: they are all finished or dropped by the time
+//! `scope` returns.
+//!
+//! Cancellation goes both ways. Dropping the `scope` future drops every task
+//! in it, and any task can cancel the whole scope with [`Scope::cancel`].
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::poll_fn,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use super::{join, JoinFuture};
+use crate::executor::{task, JoinHandle, Task};
+
+/// The error returned by a [`scope`] that was cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("scope was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// A handle for spawning futures that may borrow anything that outlives
+/// `'env`.
+///
+/// Clone it to spawn from inside a spawned future.
+#[derive(Clone)]
+pub struct Scope<'env> {
+    inner: Rc<Inner<'env>>,
+}
+
+struct Inner<'env> {
+    tasks: RefCell<HashMap<usize, (Task<'env>, Arc<TaskWaker>)>>,
+    next_id: Cell<usize>,
+    shared: Arc<Shared>,
+    body_done: Cell<bool>,
+    cancelled: Cell<bool>,
+    /// Set once `scope` has returned. Spawning after that panics.
+    closed: Cell<bool>,
+}
+
+/// The part of the scope that wakers need to reach.
+struct Shared {
+    /// Ids of tasks that have been woken and need to be polled.
+    ready: Mutex<Vec<usize>>,
+    /// The waker for the join branch that runs the tasks.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Shared {
+    fn wake(&self) {
+        if let Some(waker) = &*self.waker.lock().unwrap() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    /// Whether the task is already queued, so repeated wakes don't queue it
+    /// more than once.
+    queued: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.shared.ready.lock().unwrap().push(self.id);
+            self.shared.wake();
+        }
+    }
+}
+
+impl<'env> Scope<'env> {
+    fn new() -> Self {
+        Scope {
+            inner: Rc::new(Inner {
+                tasks: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+                shared: Arc::new(Shared {
+                    ready: Mutex::new(vec![]),
+                    waker: Mutex::new(None),
+                }),
+                body_done: Cell::new(false),
+                cancelled: Cell::new(false),
+                closed: Cell::new(false),
+            }),
+        }
+    }
+
+    /// Runs `f` concurrently with the rest of the scope.
+    ///
+    /// The scope doesn't finish until `f` does, whether or not the handle is
+    /// awaited. Dropping the handle doesn't cancel `f`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`scope`] this belongs to has already returned, since
+    /// nothing would ever run `f`.
+    pub fn spawn<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: IntoFuture + 'env,
+        F::Output: 'env,
+    {
+        assert!(
+            !self.inner.closed.get(),
+            "spawned on a scope that has returned"
+        );
+        let (task, handle) = task(f);
+        let id = self.inner.next_id.get();
+        self.inner.next_id.set(id + 1);
+        let waker = Arc::new(TaskWaker {
+            id,
+            queued: AtomicBool::new(false),
+            shared: self.inner.shared.clone(),
+        });
+        self.inner
+            .tasks
+            .borrow_mut()
+            .insert(id, (task, waker.clone()));
+        waker.wake_by_ref();
+        handle
+    }
+
+    /// Cancels the scope. The body and every spawned task are dropped at
+    /// their next `await`, and `scope` returns `Err(Cancelled)`.
+    pub fn cancel(&self) {
+        self.inner.cancelled.set(true);
+        self.inner.shared.wake();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.get()
+    }
+
+    /// Polls the tasks that were woken. Returns `Ready` once the body and
+    /// every task have finished, or the scope is cancelled.
+    fn poll_tasks(&self, cx: &mut Context<'_>) -> Poll<()> {
+        {
+            let mut waker = self.inner.shared.waker.lock().unwrap();
+            match &mut *waker {
+                Some(old) => old.clone_from(cx.waker()),
+                None => *waker = Some(cx.waker().clone()),
+            }
+        }
+        let ready = std::mem::take(&mut *self.inner.shared.ready.lock().unwrap());
+        for id in ready {
+            if self.inner.cancelled.get() {
+                break;
+            }
+            self.poll_task(id);
+        }
+
+        if self.inner.cancelled.get()
+            || (self.inner.body_done.get() && self.inner.tasks.borrow().is_empty())
+        {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_task(&self, id: usize) {
+        // Take the task out while we poll it so that it can spawn new tasks.
+        let Some((mut task, waker)) = self.inner.tasks.borrow_mut().remove(&id) else {
+            return;
+        };
+        waker.queued.store(false, Ordering::Release);
+        let cx_waker = Waker::from(waker.clone());
+        if task
+            .as_mut()
+            .poll(&mut Context::from_waker(&cx_waker))
+            .is_pending()
+        {
+            self.inner.tasks.borrow_mut().insert(id, (task, waker));
+        }
+    }
+
+    /// Drops every task that hasn't finished. Tasks may hold clones of the
+    /// scope, so this also breaks those reference cycles.
+    fn close(&self) {
+        self.inner.closed.set(true);
+        let tasks = std::mem::take(&mut *self.inner.tasks.borrow_mut());
+        drop(tasks);
+    }
+}
+
+/// Closes the scope however `scope` exits, including by being dropped.
+struct CloseOnDrop<'a, 'env>(&'a Scope<'env>);
+
+impl Drop for CloseOnDrop<'_, '_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Runs `f` with a [`Scope`] for spawning futures that borrow from outside.
+///
+/// Resolves once `f` and everything spawned on the scope have finished, to the
+/// output of `f`, or to `Err(Cancelled)` if the scope was cancelled. Either
+/// way, no spawned future is still around by then.
+pub async fn scope<'env, R>(f: impl AsyncFnOnce(&Scope<'env>) -> R) -> Result<R, Cancelled> {
+    let scope = Scope::new();
+    let _close = CloseOnDrop(&scope);
+    let mut output = None;
+    join(poll_fn(|cx| scope.poll_tasks(cx)))
+        .with(async {
+            output = Some(f(&scope).await);
+            scope.inner.body_done.set(true);
+            scope.inner.shared.wake();
+        })
+        .await;
+    match output {
+        Some(output) if !scope.is_cancelled() => Ok(output),
+        _ => Err(Cancelled),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, time::Duration};
+
+    use super::{scope, Cancelled};
+    use crate::{block_on, future_combinators::race, time::Clock, Either::Right};
+
+    #[test]
+    fn tasks_borrow_locals() {
+        let mut counts = vec![0; 5];
+        let result = block_on(scope(async |s| {
+            for count in counts.iter_mut() {
+                s.spawn(async move { *count += 1 });
+            }
+            "done"
+        }));
+        assert_eq!(result, Ok("done"));
+        assert_eq!(counts, vec![1; 5]);
+    }
+
+    #[test]
+    fn handles_return_outputs() {
+        let values = [1, 2, 3];
+        let sum = block_on(scope(async |s| {
+            let handles: Vec<_> = values
+                .iter()
+                .map(|v| s.spawn(async move { v * 10 }))
+                .collect();
+            let mut sum = 0;
+            for handle in handles {
+                sum += handle.await;
+            }
+            sum
+        }));
+        assert_eq!(sum, Ok(60));
+    }
+
+    #[test]
+    fn waits_for_unawaited_tasks() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let finished = RefCell::new(vec![]);
+        clock
+            .block_on(scope(async |s| {
+                for secs in [3, 1, 2] {
+                    let (clock, finished) = (&clock, &finished);
+                    s.spawn(async move {
+                        clock.sleep(Duration::from_secs(secs)).await;
+                        finished.borrow_mut().push(secs);
+                    });
+                }
+            }))
+            .unwrap();
+        assert_eq!(*finished.borrow(), vec![1, 2, 3]);
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn tasks_spawn_tasks() {
+        let log = RefCell::new(vec![]);
+        block_on(scope(async |s| {
+            let inner = s.clone();
+            let log = &log;
+            s.spawn(async move {
+                log.borrow_mut().push("outer");
+                inner.spawn(async move { log.borrow_mut().push("inner") });
+            });
+        }))
+        .unwrap();
+        assert_eq!(*log.borrow(), vec!["outer", "inner"]);
+    }
+
+    struct Guard<'a>(&'static str, &'a RefCell<Vec<&'static str>>);
+
+    impl Drop for Guard<'_> {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn task_cancels_scope() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let dropped = RefCell::new(vec![]);
+        let result = clock.block_on(scope(async |s| {
+            let (clock, dropped) = (&clock, &dropped);
+            s.spawn(async move {
+                let _guard = Guard("slow task", dropped);
+                clock.sleep(Duration::from_secs(10)).await;
+            });
+            let canceller = s.clone();
+            s.spawn(async move {
+                clock.sleep(Duration::from_secs(1)).await;
+                canceller.cancel();
+            });
+            let _guard = Guard("body", dropped);
+            clock.sleep(Duration::from_secs(10)).await;
+        }));
+        assert_eq!(result, Err(Cancelled));
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+        let mut dropped = dropped.take();
+        dropped.sort();
+        assert_eq!(dropped, vec!["body", "slow task"]);
+    }
+
+    #[test]
+    fn dropping_scope_cancels_tasks() {
+        let clock = Clock::new();
+        let dropped = RefCell::new(vec![]);
+        let result = clock.block_on(race(
+            scope(async |s| {
+                let (clock, dropped) = (&clock, &dropped);
+                s.spawn(async move {
+                    let _guard = Guard("task", dropped);
+                    clock.sleep(Duration::from_secs(10)).await;
+                });
+            }),
+            clock.sleep(Duration::from_secs(1)),
+        ));
+        assert!(matches!(result, Right(())));
+        assert_eq!(*dropped.borrow(), vec!["task"]);
+        assert_eq!(clock.next_deadline(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "spawned on a scope that has returned")]
+    fn spawn_after_scope_returns() {
+        let s = block_on(scope(async |s| s.clone())).unwrap();
+        s.spawn(async {});
+    }
+}
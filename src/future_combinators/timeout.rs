@@ -0,0 +1,110 @@
+//! Puts a time limit on a future.
+//!
+//! The crate has no global timer, so these take the [`Clock`] to measure
+//! against. Iterator versions live next to each iteration model, in
+//! `poll::timeout`, `afit::timeout` and `push::timeout`.
+
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::time::{Clock, Instant, Sleep};
+
+/// The error returned when a time limit runs out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+/// Runs `f`, giving up with [`Elapsed`] if it takes longer than `duration`.
+pub fn timeout<F: IntoFuture>(clock: &Clock, duration: Duration, f: F) -> Timeout<F::IntoFuture> {
+    deadline(clock, clock.now() + duration, f)
+}
+
+/// Runs `f`, giving up with [`Elapsed`] if it hasn't finished by `deadline`.
+pub fn deadline<F: IntoFuture>(clock: &Clock, deadline: Instant, f: F) -> Timeout<F::IntoFuture> {
+    Timeout {
+        future: f.into_future(),
+        sleep: clock.sleep_until(deadline),
+    }
+}
+
+/// Returned by [`timeout`] and [`deadline`].
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F> Timeout<F> {
+    pub fn deadline(&self) -> Instant {
+        self.sleep.deadline()
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        // The future goes first, so one that finishes right at the deadline
+        // still counts.
+        if let Poll::Ready(output) = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut this.sleep).poll(cx).map(|()| Err(Elapsed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{deadline, timeout, Elapsed};
+    use crate::time::Clock;
+
+    #[test]
+    fn finishes_in_time() {
+        let clock = Clock::new();
+        let f = async {
+            clock.sleep(Duration::from_secs(1)).await;
+            1
+        };
+        assert_eq!(
+            clock.block_on(timeout(&clock, Duration::from_secs(2), f)),
+            Ok(1)
+        );
+        // The timer is gone once the timeout finishes.
+        assert_eq!(clock.next_deadline(), None);
+    }
+
+    #[test]
+    fn times_out() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let f = clock.sleep(Duration::from_secs(5));
+        assert_eq!(
+            clock.block_on(timeout(&clock, Duration::from_secs(2), f)),
+            Err(Elapsed)
+        );
+        assert_eq!(clock.now() - start, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn ready_at_deadline() {
+        let clock = Clock::new();
+        let at = clock.now() + Duration::from_secs(1);
+        let f = clock.sleep_until(at);
+        assert_eq!(clock.block_on(deadline(&clock, at, f)), Ok(()));
+    }
+}
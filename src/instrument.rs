@@ -0,0 +1,254 @@
+//! Counts polls and wakeups so tests can check how much work a combinator does.
+//!
+//! Wrap a future or async iterator in [`Instrumented`], grab its [`PollStats`]
+//! handle, and then run it as usual. The counters stay readable after the
+//! wrapper has been consumed by `block_on`.
+
+use std::{
+    async_iter::AsyncIterator,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    time::{Duration, Instant},
+};
+
+pub struct Instrumented<T> {
+    inner: T,
+    stats: PollStats,
+    /// The last waker we were polled with and the counting waker that wraps it,
+    /// so we don't allocate a new wrapper on every poll.
+    waker: Option<(Waker, Waker)>,
+}
+
+/// A handle to the counters for an [`Instrumented`] value.
+#[derive(Clone, Default)]
+pub struct PollStats {
+    inner: Arc<StatsInner>,
+}
+
+#[derive(Default)]
+struct StatsInner {
+    polls: AtomicUsize,
+    pending: AtomicUsize,
+    unwoken_polls: AtomicUsize,
+    /// Whether the last poll returned `Pending`.
+    last_pending: AtomicBool,
+    wakes: AtomicUsize,
+    wakes_by_ref: AtomicUsize,
+    /// When the first wake since the last poll happened.
+    woken_at: Mutex<Option<Instant>>,
+    wake_latencies: Mutex<Vec<Duration>>,
+}
+
+impl PollStats {
+    /// Number of times the value was polled.
+    pub fn polls(&self) -> usize {
+        self.inner.polls.load(Ordering::Relaxed)
+    }
+
+    /// Number of polls that returned `Pending`.
+    pub fn pending(&self) -> usize {
+        self.inner.pending.load(Ordering::Relaxed)
+    }
+
+    /// Number of polls that followed a `Pending` without a wake in between.
+    ///
+    /// These are the polls a combinator did without being asked to.
+    pub fn unwoken_polls(&self) -> usize {
+        self.inner.unwoken_polls.load(Ordering::Relaxed)
+    }
+
+    /// Total number of wakeups, by value or by reference.
+    pub fn wakes(&self) -> usize {
+        self.wakes_by_value() + self.wakes_by_ref()
+    }
+
+    /// Number of wakeups through [`Waker::wake`].
+    pub fn wakes_by_value(&self) -> usize {
+        self.inner.wakes.load(Ordering::Relaxed)
+    }
+
+    /// Number of wakeups through [`Waker::wake_by_ref`].
+    pub fn wakes_by_ref(&self) -> usize {
+        self.inner.wakes_by_ref.load(Ordering::Relaxed)
+    }
+
+    /// For each poll that followed a wake, the time from that wake to the poll.
+    pub fn wake_latencies(&self) -> Vec<Duration> {
+        self.inner.wake_latencies.lock().unwrap().clone()
+    }
+
+    fn record_wake(&self) {
+        self.inner
+            .woken_at
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+    }
+
+    /// Records a poll. `woken_at` is the wake time taken before the poll
+    /// started, since wakes that happen during the poll are for the next one.
+    fn record_poll<T>(&self, result: &Poll<T>, woken_at: Option<Instant>) {
+        self.inner.polls.fetch_add(1, Ordering::Relaxed);
+        if result.is_pending() {
+            self.inner.pending.fetch_add(1, Ordering::Relaxed);
+        }
+        let after_pending = self
+            .inner
+            .last_pending
+            .swap(result.is_pending(), Ordering::Relaxed);
+        match woken_at {
+            Some(woken_at) => self
+                .inner
+                .wake_latencies
+                .lock()
+                .unwrap()
+                .push(woken_at.elapsed()),
+            None if after_pending => {
+                self.inner.unwoken_polls.fetch_add(1, Ordering::Relaxed);
+            }
+            None => {}
+        }
+    }
+}
+
+struct CountingWaker {
+    inner: Waker,
+    stats: PollStats,
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.stats.inner.wakes.fetch_add(1, Ordering::Relaxed);
+        self.stats.record_wake();
+        self.inner.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.stats
+            .inner
+            .wakes_by_ref
+            .fetch_add(1, Ordering::Relaxed);
+        self.stats.record_wake();
+        self.inner.wake_by_ref();
+    }
+}
+
+impl<T> Instrumented<T> {
+    pub fn new(inner: T) -> Self {
+        Instrumented {
+            inner,
+            stats: PollStats::default(),
+            waker: None,
+        }
+    }
+
+    /// Returns a handle to the counters that outlives this wrapper.
+    pub fn stats(&self) -> PollStats {
+        self.stats.clone()
+    }
+
+    /// Polls the inner value with a counting waker and records the result.
+    fn instrumented_poll<R>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        poll: impl FnOnce(Pin<&mut T>, &mut Context<'_>) -> Poll<R>,
+    ) -> Poll<R> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let stale = match &this.waker {
+            Some((outer, _)) => !outer.will_wake(cx.waker()),
+            None => true,
+        };
+        if stale {
+            let counting = Waker::from(Arc::new(CountingWaker {
+                inner: cx.waker().clone(),
+                stats: this.stats.clone(),
+            }));
+            this.waker = Some((cx.waker().clone(), counting));
+        }
+        let (_, counting) = this.waker.as_ref().unwrap();
+        let woken_at = this.stats.inner.woken_at.lock().unwrap().take();
+        let result = poll(
+            unsafe { Pin::new_unchecked(&mut this.inner) },
+            &mut Context::from_waker(counting),
+        );
+        this.stats.record_poll(&result, woken_at);
+        result
+    }
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.instrumented_poll(cx, F::poll)
+    }
+}
+
+impl<I: AsyncIterator> AsyncIterator for Instrumented<I> {
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.instrumented_poll(cx, I::poll_next)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{future::poll_fn, task::Poll};
+
+    use super::Instrumented;
+    use crate::block_on;
+
+    #[test]
+    fn counts_polls_and_wakes() {
+        let mut n = 0;
+        let f = Instrumented::new(poll_fn(|cx| {
+            n += 1;
+            match n {
+                1 => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                2 => {
+                    let waker = cx.waker().clone();
+                    waker.wake();
+                    Poll::Pending
+                }
+                _ => Poll::Ready(n),
+            }
+        }));
+        let stats = f.stats();
+        assert_eq!(block_on(f), 3);
+
+        assert_eq!(stats.polls(), 3);
+        assert_eq!(stats.pending(), 2);
+        assert_eq!(stats.wakes_by_ref(), 1);
+        assert_eq!(stats.wakes_by_value(), 1);
+        assert_eq!(stats.wakes(), 2);
+        assert_eq!(stats.unwoken_polls(), 0);
+        assert_eq!(stats.wake_latencies().len(), 2);
+    }
+
+    #[test]
+    fn counts_async_iterator_polls() {
+        let iter = Instrumented::new(async gen {
+            yield 1;
+            yield 2;
+        });
+        let stats = iter.stats();
+        block_on(async { for await _ in iter {} });
+        // Two items and the final `None`.
+        assert_eq!(stats.polls(), 3);
+        assert_eq!(stats.pending(), 0);
+        assert_eq!(stats.unwoken_polls(), 0);
+    }
+}
@@ -6,37 +6,131 @@
 
 #![feature(
     async_iterator,
-    noop_waker,
     async_for_loop,
     gen_blocks,
-    async_closure,
+    async_trait_bounds,
     impl_trait_in_assoc_type
 )]
-#![allow(unstable_features)]
+#![allow(unstable_features, async_fn_in_trait)]
 
 use std::future::Future;
 use std::pin::pin;
-use std::task::{Context, Poll};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
 
-mod afit;
-mod future_combinators;
-mod poll;
-mod push;
+pub mod afit;
+mod either;
+pub mod executor;
+pub mod future_combinators;
+pub mod instrument;
+pub mod poll;
+pub mod push;
+pub mod rng;
+pub mod time;
 
-pub enum Either<A, B> {
-    Left(A),
-    Right(B),
+pub use either::{Either, EitherOrBoth};
+
+/// Wakes the thread that is blocked in [`block_on`].
+struct ThreadWaker {
+    thread: Thread,
+    /// Set when the future has been woken since the last time it was polled.
+    ///
+    /// This keeps us from losing a wakeup that happens before we park, and lets
+    /// us ignore spurious unparks.
+    notified: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.notified.swap(true, Ordering::Release) {
+            self.thread.unpark();
+        }
+    }
+}
+
+/// Runs a future to completion on the current thread.
+///
+/// The thread parks whenever the future returns `Pending` and only polls again
+/// once the future's waker has been called. A future that returns `Pending`
+/// without arranging a wakeup will hang, which is exactly what we want in
+/// tests.
+pub fn block_on<F: IntoFuture>(f: F) -> F::Output {
+    block_on_with_clock(f, None)
 }
 
-fn block_on<F: IntoFuture>(f: F) -> F::Output {
-    let waker = std::task::Waker::noop();
+/// Implements [`block_on`] and [`time::Clock::block_on`].
+///
+/// When given a clock, we advance to the next timer deadline instead of parking
+/// whenever the future is waiting.
+fn block_on_with_clock<F: IntoFuture>(f: F, clock: Option<&time::Clock>) -> F::Output {
+    let thread_waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        notified: AtomicBool::new(false),
+    });
+    let waker = Waker::from(thread_waker.clone());
     let mut cx = Context::from_waker(&waker);
 
     let mut f = pin!(f.into_future());
     loop {
         match f.as_mut().poll(&mut cx) {
             Poll::Ready(val) => return val,
-            Poll::Pending => (),
+            Poll::Pending => {
+                while !thread_waker.notified.swap(false, Ordering::Acquire) {
+                    if clock.is_some_and(|clock| clock.advance_to_next_deadline()) {
+                        continue;
+                    }
+                    thread::park();
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::future::poll_fn;
+    use std::task::Poll;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::block_on;
+
+    #[test]
+    fn wake_from_other_thread() {
+        let mut spawned = None;
+        let result = block_on(poll_fn(|cx| match spawned {
+            None => {
+                let waker = cx.waker().clone();
+                spawned = Some(thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    waker.wake();
+                }));
+                Poll::Pending
+            }
+            Some(_) => Poll::Ready(42),
+        }));
+        assert_eq!(result, 42);
+        spawned.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn self_wake() {
+        let mut polls = 0;
+        block_on(poll_fn(|cx| {
+            polls += 1;
+            if polls < 5 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }));
+        assert_eq!(polls, 5);
+    }
+}
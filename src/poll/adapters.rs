@@ -0,0 +1,617 @@
+//! The usual iterator adapters, for `poll_next` async iterators.
+//!
+//! These mirror their namesakes on [`Iterator`] and take plain closures. For
+//! `async` closures see [`map`](super::map::map) and [`boxed`](super::boxed).
+//!
+//! Every adapter wraps its source in `Fused`, so once it has returned `None`
+//! it keeps doing so without polling the source again.
+
+use std::{
+    async_iter::AsyncIterator,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::merge::Fused;
+
+pub struct Filter<I: AsyncIterator, P> {
+    iter: Fused<I>,
+    predicate: P,
+}
+
+impl<I: AsyncIterator, P> Filter<I, P> {
+    pub(super) fn new(iter: I, predicate: P) -> Self {
+        Filter {
+            iter: Fused::new(iter),
+            predicate,
+        }
+    }
+}
+
+impl<I, P> AsyncIterator for Filter<I, P>
+where
+    I: AsyncIterator,
+    P: FnMut(&I::Item) -> bool,
+{
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        loop {
+            match iter.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) if !(this.predicate)(&item) => {}
+                otherwise => return otherwise,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+pub struct FilterMap<I: AsyncIterator, F> {
+    iter: Fused<I>,
+    f: F,
+}
+
+impl<I: AsyncIterator, F> FilterMap<I, F> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        FilterMap {
+            iter: Fused::new(iter),
+            f,
+        }
+    }
+}
+
+impl<I, F, B> AsyncIterator for FilterMap<I, F>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> Option<B>,
+{
+    type Item = B;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        loop {
+            match iter.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    if let Some(item) = (this.f)(item) {
+                        return Poll::Ready(Some(item));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+pub struct Take<I: AsyncIterator> {
+    iter: Fused<I>,
+    remaining: usize,
+}
+
+impl<I: AsyncIterator> Take<I> {
+    pub(super) fn new(iter: I, n: usize) -> Self {
+        Take {
+            iter: Fused::new(iter),
+            remaining: n,
+        }
+    }
+}
+
+impl<I: AsyncIterator> AsyncIterator for Take<I> {
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        // Don't ask for an item we aren't going to yield.
+        if this.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let poll = unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx);
+        if let Poll::Ready(Some(_)) = poll {
+            this.remaining -= 1;
+        }
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.remaining == 0 {
+            return (0, Some(0));
+        }
+        let (lower, upper) = self.iter.size_hint();
+        (
+            lower.min(self.remaining),
+            Some(upper.map_or(self.remaining, |upper| upper.min(self.remaining))),
+        )
+    }
+}
+
+pub struct Skip<I: AsyncIterator> {
+    iter: Fused<I>,
+    /// How many more items to drop before yielding any.
+    n: usize,
+}
+
+impl<I: AsyncIterator> Skip<I> {
+    pub(super) fn new(iter: I, n: usize) -> Self {
+        Skip {
+            iter: Fused::new(iter),
+            n,
+        }
+    }
+}
+
+impl<I: AsyncIterator> AsyncIterator for Skip<I> {
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        loop {
+            match iter.as_mut().poll_next(cx) {
+                Poll::Ready(Some(_)) if this.n > 0 => this.n -= 1,
+                otherwise => return otherwise,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (
+            lower.saturating_sub(self.n),
+            upper.map(|upper| upper.saturating_sub(self.n)),
+        )
+    }
+}
+
+pub struct TakeWhile<I: AsyncIterator, P> {
+    iter: Fused<I>,
+    predicate: P,
+    /// Set once the predicate has failed.
+    done: bool,
+}
+
+impl<I: AsyncIterator, P> TakeWhile<I, P> {
+    pub(super) fn new(iter: I, predicate: P) -> Self {
+        TakeWhile {
+            iter: Fused::new(iter),
+            predicate,
+            done: false,
+        }
+    }
+}
+
+impl<I, P> AsyncIterator for TakeWhile<I, P>
+where
+    I: AsyncIterator,
+    P: FnMut(&I::Item) -> bool,
+{
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            return Poll::Ready(None);
+        }
+        match unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+            Poll::Ready(Some(item)) if !(this.predicate)(&item) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+            otherwise => otherwise,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, self.iter.size_hint().1)
+        }
+    }
+}
+
+pub struct SkipWhile<I: AsyncIterator, P> {
+    iter: Fused<I>,
+    predicate: P,
+    /// Cleared once the predicate has failed.
+    skipping: bool,
+}
+
+impl<I: AsyncIterator, P> SkipWhile<I, P> {
+    pub(super) fn new(iter: I, predicate: P) -> Self {
+        SkipWhile {
+            iter: Fused::new(iter),
+            predicate,
+            skipping: true,
+        }
+    }
+}
+
+impl<I, P> AsyncIterator for SkipWhile<I, P>
+where
+    I: AsyncIterator,
+    P: FnMut(&I::Item) -> bool,
+{
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        loop {
+            match iter.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) if this.skipping => {
+                    if !(this.predicate)(&item) {
+                        this.skipping = false;
+                        return Poll::Ready(Some(item));
+                    }
+                }
+                otherwise => return otherwise,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.skipping {
+            (0, self.iter.size_hint().1)
+        } else {
+            self.iter.size_hint()
+        }
+    }
+}
+
+pub struct StepBy<I: AsyncIterator> {
+    iter: Fused<I>,
+    step: usize,
+    /// How many more items to drop before yielding the next one.
+    skip: usize,
+}
+
+impl<I: AsyncIterator> StepBy<I> {
+    pub(super) fn new(iter: I, step: usize) -> Self {
+        assert!(step != 0, "step_by needs a step of at least 1");
+        StepBy {
+            iter: Fused::new(iter),
+            step,
+            skip: 0,
+        }
+    }
+
+    /// How many items we'll yield out of the next `n` from the source.
+    fn yields_from(&self, n: usize) -> usize {
+        if n > self.skip {
+            (n - self.skip - 1) / self.step + 1
+        } else {
+            0
+        }
+    }
+}
+
+impl<I: AsyncIterator> AsyncIterator for StepBy<I> {
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        loop {
+            match iter.as_mut().poll_next(cx) {
+                Poll::Ready(Some(_)) if this.skip > 0 => this.skip -= 1,
+                Poll::Ready(Some(item)) => {
+                    this.skip = this.step - 1;
+                    return Poll::Ready(Some(item));
+                }
+                otherwise => return otherwise,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (
+            self.yields_from(lower),
+            upper.map(|upper| self.yields_from(upper)),
+        )
+    }
+}
+
+pub struct Enumerate<I: AsyncIterator> {
+    iter: Fused<I>,
+    count: usize,
+}
+
+impl<I: AsyncIterator> Enumerate<I> {
+    pub(super) fn new(iter: I) -> Self {
+        Enumerate {
+            iter: Fused::new(iter),
+            count: 0,
+        }
+    }
+}
+
+impl<I: AsyncIterator> AsyncIterator for Enumerate<I> {
+    type Item = (usize, I::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        match unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                let i = this.count;
+                this.count += 1;
+                Poll::Ready(Some((i, item)))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+pub struct Inspect<I: AsyncIterator, F> {
+    iter: Fused<I>,
+    f: F,
+}
+
+impl<I: AsyncIterator, F> Inspect<I, F> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        Inspect {
+            iter: Fused::new(iter),
+            f,
+        }
+    }
+}
+
+impl<I, F> AsyncIterator for Inspect<I, F>
+where
+    I: AsyncIterator,
+    F: FnMut(&I::Item),
+{
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let poll = unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx);
+        if let Poll::Ready(Some(item)) = &poll {
+            (this.f)(item);
+        }
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+pub struct MapWhile<I: AsyncIterator, P> {
+    iter: Fused<I>,
+    predicate: P,
+    /// Set once the predicate has returned `None`.
+    done: bool,
+}
+
+impl<I: AsyncIterator, P> MapWhile<I, P> {
+    pub(super) fn new(iter: I, predicate: P) -> Self {
+        MapWhile {
+            iter: Fused::new(iter),
+            predicate,
+            done: false,
+        }
+    }
+}
+
+impl<I, P, B> AsyncIterator for MapWhile<I, P>
+where
+    I: AsyncIterator,
+    P: FnMut(I::Item) -> Option<B>,
+{
+    type Item = B;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            return Poll::Ready(None);
+        }
+        match unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                let item = (this.predicate)(item);
+                this.done = item.is_none();
+                Poll::Ready(item)
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, self.iter.size_hint().1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        async_iter::AsyncIterator,
+        cell::{Cell, RefCell},
+        pin::{pin, Pin},
+        rc::Rc,
+        task::{Context, Poll},
+    };
+
+    use crate::{block_on, poll::AsyncIteratorExt};
+
+    /// Yields the items of `I` without ever being pending, and passes its
+    /// `size_hint` through.
+    struct FromIter<I>(I);
+
+    impl<I: Iterator + Unpin> AsyncIterator for FromIter<I> {
+        type Item = I::Item;
+
+        fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+            Poll::Ready(self.get_mut().0.next())
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.0.size_hint()
+        }
+    }
+
+    fn collect<I: AsyncIterator>(iter: I) -> Vec<I::Item> {
+        block_on(async {
+            let mut result = vec![];
+            for await item in iter {
+                result.push(item);
+            }
+            result
+        })
+    }
+
+    /// Checks that `make` builds an adapter whose `size_hint` holds at every
+    /// step and matches what `std` says for the same adapter over `0..10`.
+    fn check_size_hints<A, S>(make: impl Fn(FromIter<std::ops::Range<u32>>) -> A, std: S)
+    where
+        A: AsyncIterator,
+        S: Iterator,
+    {
+        let mut iter = pin!(make(FromIter(0..10)));
+        assert_eq!(iter.size_hint(), std.size_hint());
+        let mut remaining = collect(make(FromIter(0..10))).len();
+        block_on(async {
+            loop {
+                let (lower, upper) = iter.size_hint();
+                assert!(lower <= remaining, "{lower} > {remaining}");
+                assert!(upper.is_none_or(|upper| upper >= remaining));
+                if iter.as_mut().next().await.is_none() {
+                    break;
+                }
+                remaining -= 1;
+            }
+        });
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn adapters_match_std() {
+        let source = || FromIter(0..10);
+        let std = || 0..10u32;
+        assert_eq!(
+            collect(source().filter(|x| x % 3 == 0)),
+            std().filter(|x| x % 3 == 0).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            collect(source().filter_map(|x| x.checked_sub(7))),
+            std().filter_map(|x| x.checked_sub(7)).collect::<Vec<_>>()
+        );
+        assert_eq!(collect(source().take(3)), vec![0, 1, 2]);
+        assert_eq!(collect(source().skip(8)), vec![8, 9]);
+        assert_eq!(collect(source().take_while(|&x| x < 2)), vec![0, 1]);
+        assert_eq!(collect(source().skip_while(|&x| x < 8)), vec![8, 9]);
+        assert_eq!(collect(source().step_by(4)), vec![0, 4, 8]);
+        assert_eq!(
+            collect(source().skip(1).step_by(3).enumerate()),
+            vec![(0, 1), (1, 4), (2, 7)]
+        );
+        assert_eq!(
+            collect(source().map_while(|x| (x < 3).then_some(x * 10))),
+            vec![0, 10, 20]
+        );
+    }
+
+    #[test]
+    fn size_hints() {
+        check_size_hints(|i| i.filter(|x| x % 2 == 0), (0..10).filter(|x| x % 2 == 0));
+        check_size_hints(|i| i.filter_map(Some), (0..10).filter_map(Some));
+        check_size_hints(|i| i.take(3), (0..10).take(3));
+        check_size_hints(|i| i.take(20), (0..10).take(20));
+        check_size_hints(|i| i.skip(3), (0..10).skip(3));
+        check_size_hints(|i| i.take_while(|&x| x < 4), (0..10).take_while(|&x| x < 4));
+        check_size_hints(|i| i.skip_while(|&x| x < 4), (0..10).skip_while(|&x| x < 4));
+        check_size_hints(|i| i.step_by(3), (0..10).step_by(3));
+        check_size_hints(|i| i.skip(1).step_by(4), (0..10).skip(1).step_by(4));
+        check_size_hints(|i| i.enumerate(), (0..10).enumerate());
+        check_size_hints(|i| i.inspect(|_| {}), (0..10).inspect(|_| {}));
+        check_size_hints(|i| i.map_while(Some), (0..10).map_while(Some));
+    }
+
+    #[test]
+    fn inspect_sees_every_item() {
+        let seen = RefCell::new(vec![]);
+        let result = collect(
+            FromIter(0..4)
+                .inspect(|&x| seen.borrow_mut().push(x))
+                .skip(2),
+        );
+        assert_eq!(result, vec![2, 3]);
+        assert_eq!(*seen.borrow(), vec![0, 1, 2, 3]);
+    }
+
+    /// Ends once, then starts yielding again if polled, counting its polls.
+    struct Restarts {
+        polls: Rc<Cell<usize>>,
+    }
+
+    impl AsyncIterator for Restarts {
+        type Item = usize;
+
+        fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<usize>> {
+            let polls = self.polls.get();
+            self.polls.set(polls + 1);
+            Poll::Ready((polls > 1).then_some(polls))
+        }
+    }
+
+    #[test]
+    fn fused_after_none() {
+        fn check<A: AsyncIterator>(make: impl FnOnce(Restarts) -> A) {
+            let polls = Rc::new(Cell::new(1));
+            let mut iter = pin!(make(Restarts {
+                polls: polls.clone()
+            }));
+            block_on(async {
+                assert!(iter.as_mut().next().await.is_none());
+                assert!(iter.as_mut().next().await.is_none());
+            });
+            assert_eq!(polls.get(), 2, "polled the source after it ended");
+        }
+
+        check(|i| i.filter(|_| true));
+        check(|i| i.filter_map(Some));
+        check(|i| i.take(5));
+        check(|i| i.skip(5));
+        check(|i| i.take_while(|_| true));
+        check(|i| i.skip_while(|_| true));
+        check(|i| i.step_by(2));
+        check(|i| i.enumerate());
+        check(|i| i.inspect(|_| {}));
+        check(|i| i.map_while(Some));
+    }
+
+    #[test]
+    fn take_while_stops_polling() {
+        let polls = Rc::new(Cell::new(2));
+        let mut iter = pin!(Restarts {
+            polls: polls.clone()
+        }
+        .take_while(|&x| x < 3));
+        block_on(async {
+            assert_eq!(iter.as_mut().next().await, Some(2));
+            assert_eq!(iter.as_mut().next().await, None);
+            assert_eq!(iter.as_mut().next().await, None);
+        });
+        assert_eq!(polls.get(), 4);
+    }
+}
@@ -0,0 +1,221 @@
+//! `async` closures for `poll_next` iterators, at the cost of a box per item.
+//!
+//! [`map`](super::map::map) can't take an `async` closure, because the future
+//! it returns borrows the closure and there is no way to name its type. Here
+//! we keep the closure in an `Rc` instead, and each item gets a boxed future
+//! that holds its own handle to the closure. Nothing borrows from the
+//! combinator, so none of this needs `unsafe`.
+
+use std::{
+    async_iter::AsyncIterator,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+type Boxed<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Runs the `async` closure `f` on each item and yields its output.
+pub fn map_async<'a, I, F, U>(iter: I, f: F) -> MapAsync<'a, I, F, U>
+where
+    I: AsyncIterator,
+    F: AsyncFn(I::Item) -> U,
+{
+    MapAsync {
+        iter,
+        f: Rc::new(f),
+        future: None,
+    }
+}
+
+pub struct MapAsync<'a, I, F, U> {
+    iter: I,
+    f: Rc<F>,
+    /// The call for the item we're working on.
+    future: Option<Boxed<'a, U>>,
+}
+
+impl<'a, I, F, U> AsyncIterator for MapAsync<'a, I, F, U>
+where
+    I: AsyncIterator<Item: 'a>,
+    F: AsyncFn(I::Item) -> U + 'a,
+{
+    type Item = U;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<U>> {
+        // SAFETY: `iter` is only ever polled in place. `future` is boxed, so
+        // it's pinned whether or not we are.
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if let Some(future) = &mut this.future {
+                return match future.as_mut().poll(cx) {
+                    Poll::Ready(output) => {
+                        this.future = None;
+                        Poll::Ready(Some(output))
+                    }
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+            match unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let f = this.f.clone();
+                    this.future = Some(Box::pin(async move { (*f)(item).await }));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let in_flight = usize::from(self.future.is_some());
+        let (lower, upper) = self.iter.size_hint();
+        (
+            lower.saturating_add(in_flight),
+            upper.and_then(|upper| upper.checked_add(in_flight)),
+        )
+    }
+}
+
+/// Yields the items for which the `async` closure `f` returns true.
+pub fn filter_async<'a, I, F>(iter: I, f: F) -> FilterAsync<'a, I, F>
+where
+    I: AsyncIterator,
+    F: AsyncFn(&I::Item) -> bool,
+{
+    FilterAsync {
+        iter,
+        f: Rc::new(f),
+        future: None,
+    }
+}
+
+pub struct FilterAsync<'a, I: AsyncIterator, F> {
+    iter: I,
+    f: Rc<F>,
+    /// Tests the item we're working on, and hands it back if it passes.
+    future: Option<Boxed<'a, Option<I::Item>>>,
+}
+
+impl<'a, I, F> AsyncIterator for FilterAsync<'a, I, F>
+where
+    I: AsyncIterator<Item: 'a>,
+    F: AsyncFn(&I::Item) -> bool + 'a,
+{
+    type Item = I::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+        // SAFETY: `iter` is only ever polled in place. `future` is boxed, so
+        // it's pinned whether or not we are.
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if let Some(future) = &mut this.future {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(kept) => {
+                        this.future = None;
+                        if kept.is_some() {
+                            return Poll::Ready(kept);
+                        }
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            match unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let f = this.f.clone();
+                    this.future = Some(Box::pin(async move {
+                        let keep = (*f)(&item).await;
+                        keep.then_some(item)
+                    }));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let in_flight = usize::from(self.future.is_some());
+        let (_, upper) = self.iter.size_hint();
+        (0, upper.and_then(|upper| upper.checked_add(in_flight)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        async_iter::AsyncIterator,
+        cell::Cell,
+        task::{Context, Waker},
+        time::Duration,
+    };
+
+    use super::{filter_async, map_async};
+    use crate::{block_on, time::Clock};
+
+    #[test]
+    fn map_borrows_captures() {
+        let offset = 10;
+        let calls = Cell::new(0);
+        let iter = async gen {
+            for i in 0..3 {
+                yield i;
+            }
+        };
+        let mapped = map_async(iter, async |x| {
+            calls.set(calls.get() + 1);
+            x + offset
+        });
+        let result = block_on(async {
+            let mut result = vec![];
+            for await x in mapped {
+                result.push(x);
+            }
+            result
+        });
+        assert_eq!(result, vec![10, 11, 12]);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn filter_borrows_item() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let iter = async gen {
+            for word in ["a", "bb", "ccc", "dddd"] {
+                yield String::from(word);
+            }
+        };
+        let filtered = filter_async(iter, async |word: &String| {
+            clock.sleep(Duration::from_secs(1)).await;
+            word.len().is_multiple_of(2)
+        });
+        let result = clock.block_on(async {
+            let mut result = vec![];
+            for await word in filtered {
+                result.push(word);
+            }
+            result
+        });
+        assert_eq!(result, vec!["bb", "dddd"]);
+        assert_eq!(clock.now() - start, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn dropped_mid_item() {
+        let clock = Clock::new();
+        let iter = async gen {
+            yield String::from("a");
+            yield String::from("b");
+        };
+        let mut filtered = Box::pin(filter_async(iter, async |word: &String| {
+            clock.sleep(Duration::from_secs(1)).await;
+            !word.is_empty()
+        }));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(filtered.as_mut().poll_next(&mut cx).is_pending());
+        // The item and the closure are dropped along with the future testing it.
+        drop(filtered);
+    }
+}
@@ -0,0 +1,767 @@
+//! Futures that consume a `poll_next` async iterator.
+//!
+//! These mirror the consuming methods on [`Iterator`]. Each one is a named
+//! future rather than an `async fn`, so it can be stored in a struct or raced
+//! against something else.
+//!
+//! They all take the iterator by value. To keep using it afterwards, pass
+//! `iter.as_mut()` on a pinned iterator instead.
+
+use std::{
+    async_iter::AsyncIterator,
+    future::Future,
+    ops::ControlFlow,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Feeds items to `f` until it breaks, which gives `Some`, or `iter` runs out,
+/// which gives `None`. Panics if called again after either of those.
+fn drive<I, T>(
+    mut iter: Pin<&mut I>,
+    done: &mut bool,
+    cx: &mut Context<'_>,
+    mut f: impl FnMut(I::Item) -> ControlFlow<T>,
+) -> Poll<Option<T>>
+where
+    I: AsyncIterator + ?Sized,
+{
+    assert!(!*done, "{POLLED_AFTER_COMPLETION}");
+    loop {
+        match iter.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                if let ControlFlow::Break(output) = f(item) {
+                    *done = true;
+                    return Poll::Ready(Some(output));
+                }
+            }
+            Poll::Ready(None) => {
+                *done = true;
+                return Poll::Ready(None);
+            }
+            Poll::Pending => return Poll::Pending,
+        }
+    }
+}
+
+const POLLED_AFTER_COMPLETION: &str = "consumer future polled after completion";
+
+pub struct Collect<I, C> {
+    iter: I,
+    done: bool,
+    collection: Option<C>,
+}
+
+impl<I, C: Default> Collect<I, C> {
+    pub(super) fn new(iter: I) -> Self {
+        Collect {
+            iter,
+            done: false,
+            collection: Some(C::default()),
+        }
+    }
+}
+
+impl<I, C> Future for Collect<I, C>
+where
+    I: AsyncIterator,
+    C: Extend<I::Item>,
+{
+    type Output = C;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<C> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let collection = this.collection.as_mut().expect(POLLED_AFTER_COMPLETION);
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        match drive(iter, &mut this.done, cx, |item| {
+            collection.extend(Some(item));
+            ControlFlow::<()>::Continue(())
+        }) {
+            Poll::Ready(_) => Poll::Ready(this.collection.take().unwrap()),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct Fold<I, B, F> {
+    iter: I,
+    done: bool,
+    acc: Option<B>,
+    f: F,
+}
+
+impl<I, B, F> Fold<I, B, F> {
+    pub(super) fn new(iter: I, init: B, f: F) -> Self {
+        Fold {
+            iter,
+            done: false,
+            acc: Some(init),
+            f,
+        }
+    }
+}
+
+impl<I, B, F> Future for Fold<I, B, F>
+where
+    I: AsyncIterator,
+    F: FnMut(B, I::Item) -> B,
+{
+    type Output = B;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<B> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let (acc, f) = (&mut this.acc, &mut this.f);
+        match drive(iter, &mut this.done, cx, |item| {
+            let prev = acc.take().expect(POLLED_AFTER_COMPLETION);
+            *acc = Some(f(prev, item));
+            ControlFlow::<()>::Continue(())
+        }) {
+            Poll::Ready(_) => Poll::Ready(acc.take().expect(POLLED_AFTER_COMPLETION)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct TryFold<I, B, F> {
+    iter: I,
+    done: bool,
+    acc: Option<B>,
+    f: F,
+}
+
+impl<I, B, F> TryFold<I, B, F> {
+    pub(super) fn new(iter: I, init: B, f: F) -> Self {
+        TryFold {
+            iter,
+            done: false,
+            acc: Some(init),
+            f,
+        }
+    }
+}
+
+impl<I, B, E, F> Future for TryFold<I, B, F>
+where
+    I: AsyncIterator,
+    F: FnMut(B, I::Item) -> Result<B, E>,
+{
+    type Output = Result<B, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<B, E>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let (acc, f) = (&mut this.acc, &mut this.f);
+        match drive(iter, &mut this.done, cx, |item| {
+            let prev = acc.take().expect(POLLED_AFTER_COMPLETION);
+            match f(prev, item) {
+                Ok(next) => {
+                    *acc = Some(next);
+                    ControlFlow::Continue(())
+                }
+                Err(error) => ControlFlow::Break(error),
+            }
+        }) {
+            Poll::Ready(Some(error)) => Poll::Ready(Err(error)),
+            Poll::Ready(None) => Poll::Ready(Ok(acc.take().expect(POLLED_AFTER_COMPLETION))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct Count<I> {
+    iter: I,
+    done: bool,
+    count: usize,
+}
+
+impl<I> Count<I> {
+    pub(super) fn new(iter: I) -> Self {
+        Count {
+            iter,
+            done: false,
+            count: 0,
+        }
+    }
+}
+
+impl<I: AsyncIterator> Future for Count<I> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let count = &mut this.count;
+        drive(iter, &mut this.done, cx, |_| {
+            *count += 1;
+            ControlFlow::<()>::Continue(())
+        })
+        .map(|_| *count)
+    }
+}
+
+pub struct Last<I: AsyncIterator> {
+    iter: I,
+    done: bool,
+    last: Option<I::Item>,
+}
+
+impl<I: AsyncIterator> Last<I> {
+    pub(super) fn new(iter: I) -> Self {
+        Last {
+            iter,
+            done: false,
+            last: None,
+        }
+    }
+}
+
+impl<I: AsyncIterator> Future for Last<I> {
+    type Output = Option<I::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let last = &mut this.last;
+        drive(iter, &mut this.done, cx, |item| {
+            *last = Some(item);
+            ControlFlow::<()>::Continue(())
+        })
+        .map(|_| last.take())
+    }
+}
+
+pub struct Nth<I> {
+    iter: I,
+    done: bool,
+    /// How many more items to drop before the one we want.
+    n: usize,
+}
+
+impl<I> Nth<I> {
+    pub(super) fn new(iter: I, n: usize) -> Self {
+        Nth {
+            iter,
+            done: false,
+            n,
+        }
+    }
+}
+
+impl<I: AsyncIterator> Future for Nth<I> {
+    type Output = Option<I::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let n = &mut this.n;
+        drive(iter, &mut this.done, cx, |item| {
+            if *n == 0 {
+                return ControlFlow::Break(item);
+            }
+            *n -= 1;
+            ControlFlow::Continue(())
+        })
+    }
+}
+
+pub struct Any<I, F> {
+    iter: I,
+    done: bool,
+    f: F,
+}
+
+impl<I, F> Any<I, F> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        Any {
+            iter,
+            done: false,
+            f,
+        }
+    }
+}
+
+impl<I, F> Future for Any<I, F>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> bool,
+{
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let f = &mut this.f;
+        drive(iter, &mut this.done, cx, |item| {
+            if f(item) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .map(|found| found.is_some())
+    }
+}
+
+pub struct All<I, F> {
+    iter: I,
+    done: bool,
+    f: F,
+}
+
+impl<I, F> All<I, F> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        All {
+            iter,
+            done: false,
+            f,
+        }
+    }
+}
+
+impl<I, F> Future for All<I, F>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> bool,
+{
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let f = &mut this.f;
+        drive(iter, &mut this.done, cx, |item| {
+            if f(item) {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        })
+        .map(|failed| failed.is_none())
+    }
+}
+
+pub struct Find<I, P> {
+    iter: I,
+    done: bool,
+    predicate: P,
+}
+
+impl<I, P> Find<I, P> {
+    pub(super) fn new(iter: I, predicate: P) -> Self {
+        Find {
+            iter,
+            done: false,
+            predicate,
+        }
+    }
+}
+
+impl<I, P> Future for Find<I, P>
+where
+    I: AsyncIterator,
+    P: FnMut(&I::Item) -> bool,
+{
+    type Output = Option<I::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let predicate = &mut this.predicate;
+        drive(iter, &mut this.done, cx, |item| {
+            if predicate(&item) {
+                ControlFlow::Break(item)
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+    }
+}
+
+pub struct FindMap<I, F> {
+    iter: I,
+    done: bool,
+    f: F,
+}
+
+impl<I, F> FindMap<I, F> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        FindMap {
+            iter,
+            done: false,
+            f,
+        }
+    }
+}
+
+impl<I, F, B> Future for FindMap<I, F>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> Option<B>,
+{
+    type Output = Option<B>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<B>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let f = &mut this.f;
+        drive(iter, &mut this.done, cx, |item| match f(item) {
+            Some(found) => ControlFlow::Break(found),
+            None => ControlFlow::Continue(()),
+        })
+    }
+}
+
+pub struct Position<I, P> {
+    iter: I,
+    done: bool,
+    predicate: P,
+    index: usize,
+}
+
+impl<I, P> Position<I, P> {
+    pub(super) fn new(iter: I, predicate: P) -> Self {
+        Position {
+            iter,
+            done: false,
+            predicate,
+            index: 0,
+        }
+    }
+}
+
+impl<I, P> Future for Position<I, P>
+where
+    I: AsyncIterator,
+    P: FnMut(I::Item) -> bool,
+{
+    type Output = Option<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<usize>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let (predicate, index) = (&mut this.predicate, &mut this.index);
+        drive(iter, &mut this.done, cx, |item| {
+            if predicate(item) {
+                return ControlFlow::Break(*index);
+            }
+            *index += 1;
+            ControlFlow::Continue(())
+        })
+    }
+}
+
+/// Shared by [`MinByKey`] and [`MaxByKey`].
+struct ByKey<I: AsyncIterator, F, K> {
+    iter: I,
+    done: bool,
+    f: F,
+    best: Option<(K, I::Item)>,
+}
+
+impl<I, F, K> ByKey<I, F, K>
+where
+    I: AsyncIterator,
+    F: FnMut(&I::Item) -> K,
+{
+    /// Keeps the item whose key `replace(best, key)` prefers.
+    fn poll_best(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        replace: impl Fn(&K, &K) -> bool,
+    ) -> Poll<Option<I::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let (f, best) = (&mut this.f, &mut this.best);
+        drive(iter, &mut this.done, cx, |item| {
+            let key = f(&item);
+            if best.as_ref().is_none_or(|(best, _)| replace(best, &key)) {
+                *best = Some((key, item));
+            }
+            ControlFlow::<()>::Continue(())
+        })
+        .map(|_| best.take().map(|(_, item)| item))
+    }
+}
+
+pub struct MinByKey<I: AsyncIterator, F, K> {
+    inner: ByKey<I, F, K>,
+}
+
+impl<I: AsyncIterator, F, K> MinByKey<I, F, K> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        MinByKey {
+            inner: ByKey {
+                iter,
+                done: false,
+                f,
+                best: None,
+            },
+        }
+    }
+}
+
+impl<I, F, K> Future for MinByKey<I, F, K>
+where
+    I: AsyncIterator,
+    F: FnMut(&I::Item) -> K,
+    K: Ord,
+{
+    type Output = Option<I::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Like `Iterator::min_by_key`, the first of several equal minimums wins.
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }
+            .poll_best(cx, |best, key| key < best)
+    }
+}
+
+pub struct MaxByKey<I: AsyncIterator, F, K> {
+    inner: ByKey<I, F, K>,
+}
+
+impl<I: AsyncIterator, F, K> MaxByKey<I, F, K> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        MaxByKey {
+            inner: ByKey {
+                iter,
+                done: false,
+                f,
+                best: None,
+            },
+        }
+    }
+}
+
+impl<I, F, K> Future for MaxByKey<I, F, K>
+where
+    I: AsyncIterator,
+    F: FnMut(&I::Item) -> K,
+    K: Ord,
+{
+    type Output = Option<I::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Like `Iterator::max_by_key`, the last of several equal maximums wins.
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }
+            .poll_best(cx, |best, key| key >= best)
+    }
+}
+
+pub struct ForEach<I, F> {
+    iter: I,
+    done: bool,
+    f: F,
+}
+
+impl<I, F> ForEach<I, F> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        ForEach {
+            iter,
+            done: false,
+            f,
+        }
+    }
+}
+
+impl<I, F> Future for ForEach<I, F>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item),
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let f = &mut this.f;
+        drive(iter, &mut this.done, cx, |item| {
+            f(item);
+            ControlFlow::<()>::Continue(())
+        })
+        .map(|_| ())
+    }
+}
+
+pub struct TryForEach<I, F> {
+    iter: I,
+    done: bool,
+    f: F,
+}
+
+impl<I, F> TryForEach<I, F> {
+    pub(super) fn new(iter: I, f: F) -> Self {
+        TryForEach {
+            iter,
+            done: false,
+            f,
+        }
+    }
+}
+
+impl<I, F, E> Future for TryForEach<I, F>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> Result<(), E>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), E>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let iter = unsafe { Pin::new_unchecked(&mut this.iter) };
+        let f = &mut this.f;
+        drive(iter, &mut this.done, cx, |item| match f(item) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(error) => ControlFlow::Break(error),
+        })
+        .map(|error| error.map_or(Ok(()), Err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, collections::BTreeSet, pin::pin, time::Duration};
+
+    use crate::{
+        block_on,
+        future_combinators::race,
+        poll::AsyncIteratorExt,
+        time::Clock,
+        Either::{Left, Right},
+    };
+
+    fn numbers(n: u32) -> impl std::async_iter::AsyncIterator<Item = u32> {
+        async gen move {
+            for i in 0..n {
+                yield i;
+            }
+        }
+    }
+
+    #[test]
+    fn reductions() {
+        block_on(async {
+            assert_eq!(numbers(4).collect::<Vec<_>>().await, vec![0, 1, 2, 3]);
+            assert_eq!(
+                numbers(3).collect::<BTreeSet<_>>().await,
+                BTreeSet::from([0, 1, 2])
+            );
+            assert_eq!(numbers(5).fold(0, |acc, x| acc + x).await, 10);
+            assert_eq!(numbers(7).count().await, 7);
+            assert_eq!(numbers(7).last().await, Some(6));
+            assert_eq!(numbers(0).last().await, None);
+            assert_eq!(numbers(7).nth(2).await, Some(2));
+            assert_eq!(numbers(2).nth(2).await, None);
+        });
+    }
+
+    #[test]
+    fn try_reductions() {
+        block_on(async {
+            let checked_sum = |acc: u32, x| acc.checked_add(x).ok_or(x);
+            assert_eq!(numbers(5).try_fold(0, checked_sum).await, Ok(10));
+            assert_eq!(numbers(5).try_fold(u32::MAX - 1, checked_sum).await, Err(2));
+
+            let mut seen = vec![];
+            let result = numbers(5)
+                .try_for_each(|x| {
+                    seen.push(x);
+                    if x < 2 {
+                        Ok(())
+                    } else {
+                        Err(x)
+                    }
+                })
+                .await;
+            assert_eq!(result, Err(2));
+            assert_eq!(seen, vec![0, 1, 2]);
+
+            let mut sum = 0;
+            numbers(4).for_each(|x| sum += x).await;
+            assert_eq!(sum, 6);
+        });
+    }
+
+    #[test]
+    fn searches_stop_early() {
+        let pulled = Cell::new(0);
+        let counted = || numbers(10).inspect(|_| pulled.set(pulled.get() + 1));
+        block_on(async {
+            assert!(counted().any(|x| x == 2).await);
+            assert_eq!(pulled.replace(0), 3);
+            assert!(!counted().all(|x| x < 2).await);
+            assert_eq!(pulled.replace(0), 3);
+            assert_eq!(counted().find(|&x| x > 3).await, Some(4));
+            assert_eq!(pulled.replace(0), 5);
+            assert_eq!(
+                counted().find_map(|x| (x > 3).then_some(x * 2)).await,
+                Some(8)
+            );
+            assert_eq!(counted().position(|x| x == 6).await, Some(6));
+
+            assert!(!numbers(3).any(|x| x > 5).await);
+            assert!(numbers(3).all(|x| x < 5).await);
+            assert_eq!(numbers(3).find(|&x| x > 5).await, None);
+            assert_eq!(numbers(3).position(|x| x > 5).await, None);
+        });
+    }
+
+    #[test]
+    fn min_and_max_by_key() {
+        let words = || async gen {
+            for word in ["bb", "a", "cc", "d"] {
+                yield word;
+            }
+        };
+        block_on(async {
+            assert_eq!(words().min_by_key(|w| w.len()).await, Some("a"));
+            assert_eq!(words().max_by_key(|w| w.len()).await, Some("cc"));
+            assert_eq!(numbers(0).min_by_key(|&x| x).await, None);
+        });
+    }
+
+    #[test]
+    fn resumes_a_pinned_iterator() {
+        block_on(async {
+            let mut iter = pin!(numbers(6));
+            assert_eq!(iter.as_mut().position(|x| x == 1).await, Some(1));
+            assert_eq!(iter.as_mut().nth(1).await, Some(3));
+            assert_eq!(iter.count().await, 2);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "polled after completion")]
+    fn panics_when_polled_after_completion() {
+        let mut count = pin!(numbers(2).count());
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        assert_eq!(count.as_mut().poll(&mut cx), std::task::Poll::Ready(2));
+        let _ = count.as_mut().poll(&mut cx);
+    }
+
+    /// Consumers are named futures, so they can be kept in a struct.
+    struct Totals<I: std::async_iter::AsyncIterator> {
+        count: super::Count<I>,
+    }
+
+    #[test]
+    fn stored_and_raced() {
+        let clock = Clock::new();
+        let slow = |n| {
+            let clock = &clock;
+            async gen move {
+                for i in 0..n {
+                    clock.sleep(Duration::from_secs(1)).await;
+                    yield i;
+                }
+            }
+        };
+        let totals = Totals {
+            count: slow(3).count(),
+        };
+        let result = clock.block_on(race(totals.count, slow(5).fold(0, |a, b| a + b)));
+        assert_eq!(result, Left(3));
+        let result = clock.block_on(race(slow(5).count(), slow(2).last()));
+        assert_eq!(result, Right(Some(1)));
+    }
+}
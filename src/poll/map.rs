@@ -1,21 +1,192 @@
-//! Implement map for poll_next async iterators, using async closures.
+//! Implement map for poll_next async iterators.
 //!
-//! This one is actually impossible because we have no way to name the lifetime
-//! in a future that results from calling an async closure.
+//! An `async` closure can't be used here, because the future it returns may
+//! borrow from the closure and we have no way to name that lifetime. A plain
+//! closure that returns a future is fine though: the future is its own type,
+//! `Fut`, and we keep the one in flight in a slot next to the iterator.
+//!
+//! The closure can't lend its captures to the future, so the usual shape is
+//! `|x| async move { ... }`.
 
-use std::async_iter::AsyncIterator;
+use std::{
+    async_iter::AsyncIterator,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
 
-pub fn map<Iter: AsyncIterator, F: async Fn(Iter::Item) -> U, U>(
-    iter: Iter,
-    f: F,
-) -> Map<Iter, F, U> {
-    Map { iter, f }
+/// Runs `f` on each item and yields the output of the future it returns.
+///
+/// Each future is run to completion before the next item is pulled from
+/// `iter`.
+pub fn map<I, F, Fut>(iter: I, f: F) -> Map<I, F, Fut>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future,
+{
+    Map {
+        iter,
+        f,
+        future: None,
+    }
 }
 
-pub struct Map<Iter: AsyncIterator, F, U>
+/// Runs `f` on each item and yields the output of the future it returns.
+///
+/// This is the name `futures::StreamExt` uses for the future-returning form.
+/// Here [`map`] already takes a future-returning closure, so the two behave
+/// the same.
+pub fn then<I, F, Fut>(iter: I, f: F) -> Map<I, F, Fut>
 where
-    F: async Fn(Iter::Item) -> U,
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future,
 {
-    iter: Iter,
+    map(iter, f)
+}
+
+pub struct Map<I, F, Fut> {
+    iter: I,
     f: F,
+    /// The future for the item we're working on. Pinned along with `Map`.
+    future: Option<Fut>,
+}
+
+impl<I, F, Fut> AsyncIterator for Map<I, F, Fut>
+where
+    I: AsyncIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future,
+{
+    type Item = Fut::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // SAFETY: `iter` and `future` are only ever polled in place. A
+        // finished future is dropped in place by overwriting the slot.
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if let Some(future) = &mut this.future {
+                return match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                    Poll::Ready(output) => {
+                        this.future = None;
+                        Poll::Ready(Some(output))
+                    }
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+            match unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+                Poll::Ready(Some(item)) => this.future = Some((this.f)(item)),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let in_flight = usize::from(self.future.is_some());
+        let (lower, upper) = self.iter.size_hint();
+        (
+            lower.saturating_add(in_flight),
+            upper.and_then(|upper| upper.checked_add(in_flight)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        async_iter::AsyncIterator,
+        future::ready,
+        pin::pin,
+        task::{Context, Waker},
+        time::Duration,
+    };
+
+    use super::{map, then};
+    use crate::{
+        block_on, future_combinators::join_all_unordered, poll::AsyncIteratorExt, time::Clock,
+    };
+
+    #[test]
+    fn simple_map() {
+        let iter = async gen {
+            for i in 0..3 {
+                yield i;
+            }
+        };
+        let result = block_on(async {
+            let mut result = vec![];
+            for await x in map(iter, |x| async move { x + 1 }) {
+                result.push(x);
+            }
+            result
+        });
+        assert_eq!(result, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn async_closure() {
+        let iter = async gen {
+            yield "a";
+            yield "bc";
+        };
+        let result = block_on(async {
+            let mut result = vec![];
+            for await len in map(iter, async |s: &str| s.len()) {
+                result.push(len);
+            }
+            result
+        });
+        assert_eq!(result, vec![1, 2]);
+    }
+
+    #[test]
+    fn then_waits_for_each_future() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let iter = async gen {
+            yield 3;
+            yield 1;
+            yield 2;
+        };
+        let mut calls = 0;
+        let mapped = then(iter, |secs| {
+            calls += 1;
+            let clock = &clock;
+            async move {
+                clock.sleep(Duration::from_secs(secs)).await;
+                (calls, clock.now() - start)
+            }
+        });
+        let result = clock.block_on(async {
+            let mut result = vec![];
+            for await x in mapped {
+                result.push(x);
+            }
+            result
+        });
+        let secs = Duration::from_secs;
+        assert_eq!(result, vec![(1, secs(3)), (2, secs(4)), (3, secs(6))]);
+    }
+
+    #[test]
+    fn size_hint_counts_in_flight_future() {
+        let clock = Clock::new();
+        let secs = [1, 2, 3].map(|secs| ready(Duration::from_secs(secs)));
+        let mut mapped = pin!(map(join_all_unordered(secs), |duration| {
+            clock.sleep(duration)
+        }));
+        assert_eq!(mapped.size_hint(), (3, Some(3)));
+
+        // Pulls the first item and starts sleeping on it.
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(mapped.as_mut().poll_next(&mut cx).is_pending());
+        assert_eq!(mapped.size_hint(), (3, Some(3)));
+
+        clock.block_on(async {
+            assert_eq!(mapped.as_mut().next().await, Some(()));
+            assert_eq!(mapped.size_hint(), (2, Some(2)));
+        });
+    }
 }
@@ -9,23 +9,30 @@ use std::{
     task::{Context, Poll},
 };
 
-use crate::Either;
+use crate::{
+    future_combinators::{Fairness, Turns},
+    Either,
+};
 
 pub fn merge<A, B>(a: A, b: B) -> Merge<A, B>
+where
+    A: AsyncIterator,
+    B: AsyncIterator,
+{
+    merge_with(a, b, Fairness::RoundRobin)
+}
+
+/// Like [`merge`], but `fairness` decides which stream is polled first each
+/// time.
+pub fn merge_with<A, B>(a: A, b: B, fairness: Fairness) -> Merge<A, B>
 where
     A: AsyncIterator,
     B: AsyncIterator,
 {
     Merge {
-        a: Fused {
-            inner: a,
-            done: false,
-        },
-        b: Fused {
-            inner: b,
-            done: false,
-        },
-        parity: true,
+        a: Fused::new(a),
+        b: Fused::new(b),
+        turns: Turns::new(fairness),
     }
 }
 
@@ -36,10 +43,10 @@ where
 {
     a: Fused<A>,
     b: Fused<B>,
-    /// Specifies whether to poll a or b first
+    /// Decides whether to poll a or b first.
     ///
     /// We do this to prevent starvation if one stream is faster than the other.
-    parity: bool,
+    turns: Turns,
 }
 
 impl<A, B> AsyncIterator for Merge<A, B>
@@ -49,56 +56,53 @@ where
 {
     type Item = Either<A::Item, B::Item>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        unsafe {
-            if self.parity {
-                // Poll a then b
-                match self
-                    .as_mut()
-                    .map_unchecked_mut(|this| &mut this.a)
-                    .poll_next(cx)
-                {
-                    Poll::Ready(Some(a)) => Poll::Ready(Some(Either::Left(a))),
-                    Poll::Ready(None) => match self
-                        .as_mut()
-                        .map_unchecked_mut(|this| &mut this.b)
-                        .poll_next(cx)
-                    {
-                        Poll::Ready(Some(b)) => Poll::Ready(Some(Either::Right(b))),
-                        Poll::Ready(None) => Poll::Ready(None),
-                        Poll::Pending => Poll::Pending,
-                    },
-                    Poll::Pending => Poll::Pending,
-                }
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut a = unsafe { Pin::new_unchecked(&mut this.a) };
+        let mut b = unsafe { Pin::new_unchecked(&mut this.b) };
+        let left_first = this.turns.left_first();
+
+        // Poll both sides, even if the first one is pending, so that each of
+        // them has our waker. We're only done once both of them are.
+        let mut done = true;
+        for left in [left_first, !left_first] {
+            let poll = if left {
+                a.as_mut().poll_next(cx).map(|a| a.map(Either::Left))
             } else {
-                // Poll b then a
-                match self
-                    .as_mut()
-                    .map_unchecked_mut(|this| &mut this.b)
-                    .poll_next(cx)
-                {
-                    Poll::Ready(Some(b)) => Poll::Ready(Some(Either::Right(b))),
-                    Poll::Ready(None) => match self
-                        .as_mut()
-                        .map_unchecked_mut(|this| &mut this.a)
-                        .poll_next(cx)
-                    {
-                        Poll::Ready(Some(a)) => Poll::Ready(Some(Either::Left(a))),
-                        Poll::Ready(None) => Poll::Ready(None),
-                        Poll::Pending => Poll::Pending,
-                    },
-                    Poll::Pending => Poll::Pending,
-                }
+                b.as_mut().poll_next(cx).map(|b| b.map(Either::Right))
+            };
+            match poll {
+                Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                Poll::Ready(None) => {}
+                Poll::Pending => done = false,
             }
         }
+        if done {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
     }
 }
 
-struct Fused<T: AsyncIterator> {
+/// Returns `None` forever once the inner iterator has, without polling it
+/// again.
+pub(super) struct Fused<T: AsyncIterator> {
     inner: T,
     done: bool,
 }
 
+impl<T: AsyncIterator> Fused<T> {
+    pub(super) fn new(inner: T) -> Self {
+        Fused { inner, done: false }
+    }
+
+    /// Whether the inner iterator has returned `None`.
+    pub(super) fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
 impl<T: AsyncIterator> AsyncIterator for Fused<T> {
     type Item = T::Item;
 
@@ -121,14 +125,26 @@ impl<T: AsyncIterator> AsyncIterator for Fused<T> {
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            self.inner.size_hint()
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use std::vec;
+    use std::{async_iter::AsyncIterator, future::poll_fn, pin::pin, task::Poll};
 
-    use super::merge;
+    use super::{merge, merge_with};
     use crate::block_on;
+    use crate::executor::{random::sweep, RandomExecutor};
+    use crate::future_combinators::Fairness;
+    use crate::instrument::Instrumented;
+    use crate::poll::AsyncIteratorExt;
     use crate::Either::{Left, Right};
 
     #[test]
@@ -160,4 +176,175 @@ mod test {
             }
         })
     }
+
+    #[test]
+    fn merge_keeps_track_of_sides() {
+        block_on(async {
+            let a = async gen {
+                yield 1;
+                yield 2;
+            };
+            let b = async gen {
+                yield 3;
+            };
+
+            let mut lefts = vec![];
+            let mut all = vec![];
+            for await item in merge(a, b) {
+                if item.is_left() {
+                    lefts.push(*item.as_ref().into_inner());
+                }
+                all.push(item.into_inner());
+            }
+
+            all.sort();
+            assert_eq!(lefts, vec![1, 2]);
+            assert_eq!(all, vec![1, 2, 3]);
+        })
+    }
+
+    #[test]
+    fn merge_random_schedules() {
+        sweep(1000, |seed| {
+            let ex = RandomExecutor::new(seed);
+            let a = ex.interrupt(async gen {
+                yield 1;
+                yield 2;
+                yield 3;
+            });
+            let b = ex.interrupt(async gen {
+                yield 4;
+                yield 5;
+                yield 6;
+            });
+
+            let (mut left, mut right) = (vec![], vec![]);
+            ex.run_until(async {
+                for await item in merge(a, b) {
+                    match item {
+                        Left(a) => left.push(a),
+                        Right(b) => right.push(b),
+                    }
+                }
+            });
+
+            assert_eq!(left, vec![1, 2, 3]);
+            assert_eq!(right, vec![4, 5, 6]);
+        });
+    }
+
+    #[test]
+    fn merge_poll_counts() {
+        let a = Instrumented::new(async gen {
+            yield 1;
+            yield 2;
+            yield 3;
+        });
+        let b = Instrumented::new(async gen {
+            yield 4;
+            yield 5;
+            yield 6;
+        });
+        let (a_stats, b_stats) = (a.stats(), b.stats());
+
+        let count = block_on(async {
+            let mut count = 0;
+            for await _ in merge(a, b) {
+                count += 1;
+            }
+            count
+        });
+
+        assert_eq!(count, 6);
+        // Each side is polled once per item plus once for its final `None`.
+        assert!(a_stats.polls() <= 4);
+        assert!(b_stats.polls() <= 4);
+        assert_eq!(a_stats.unwoken_polls() + b_stats.unwoken_polls(), 0);
+    }
+
+    /// A stream that always has an item ready.
+    fn fast() -> impl AsyncIterator<Item = u32> {
+        async gen {
+            loop {
+                yield 0;
+            }
+        }
+    }
+
+    /// A stream that is pending for `polls` polls before each item.
+    fn slow(polls: usize) -> impl AsyncIterator<Item = u32> {
+        async gen move {
+            loop {
+                let mut remaining = polls;
+                poll_fn(|cx| {
+                    if remaining == 0 {
+                        return Poll::Ready(());
+                    }
+                    remaining -= 1;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                })
+                .await;
+                yield 1;
+            }
+        }
+    }
+
+    /// Takes the first `n` items of the merged streams and counts how many
+    /// came from the right.
+    fn right_count(
+        a: impl AsyncIterator<Item = u32>,
+        b: impl AsyncIterator<Item = u32>,
+        fairness: Fairness,
+        n: usize,
+    ) -> usize {
+        block_on(async {
+            let mut merged = pin!(merge_with(a, b, fairness));
+            let mut right = 0;
+            for _ in 0..n {
+                if merged.as_mut().next().await.unwrap().is_right() {
+                    right += 1;
+                }
+            }
+            right
+        })
+    }
+
+    #[test]
+    fn fairness_between_ready_streams() {
+        assert_eq!(right_count(fast(), fast(), Fairness::Biased, 40), 0);
+        assert_eq!(right_count(fast(), fast(), Fairness::RoundRobin, 40), 20);
+        assert_eq!(
+            right_count(fast(), fast(), Fairness::Weighted(3, 1), 40),
+            10
+        );
+        let random = right_count(fast(), fast(), Fairness::Random(1), 40);
+        assert!(random > 0 && random < 40);
+    }
+
+    #[test]
+    fn slow_stream_is_not_starved() {
+        for fairness in [
+            Fairness::RoundRobin,
+            Fairness::Weighted(3, 1),
+            Fairness::Weighted(1, 3),
+            Fairness::Random(1),
+        ] {
+            assert!(
+                right_count(fast(), slow(3), fairness, 100) > 0,
+                "{fairness:?} starved the slow stream"
+            );
+        }
+        // Biased is for when starving the right side is what you want.
+        assert_eq!(right_count(fast(), slow(3), Fairness::Biased, 100), 0);
+    }
+
+    #[test]
+    fn pending_side_does_not_block_the_other() {
+        let never = async gen {
+            poll_fn(|_cx| Poll::<()>::Pending).await;
+            yield 0;
+        };
+        assert_eq!(right_count(never, fast(), Fairness::Biased, 10), 10);
+    }
 }
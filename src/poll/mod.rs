@@ -1,13 +1,265 @@
 use std::{async_iter::AsyncIterator, future::poll_fn, pin::Pin};
 
-// map is impossible
-// pub mod map;
+pub mod adapters;
+pub mod boxed;
+pub mod consumers;
+pub mod map;
 pub mod merge;
+pub mod timeout;
+pub mod zip;
+
+use adapters::{
+    Enumerate, Filter, FilterMap, Inspect, MapWhile, Skip, SkipWhile, StepBy, Take, TakeWhile,
+};
+pub use boxed::{filter_async, map_async};
+use consumers::{
+    All, Any, Collect, Count, Find, FindMap, Fold, ForEach, Last, MaxByKey, MinByKey, Nth,
+    Position, TryFold, TryForEach,
+};
+pub use zip::{unzip, zip, zip_longest};
+use zip::{UnzipLeft, UnzipRight};
 
 pub trait AsyncIteratorExt: AsyncIterator {
     async fn next(mut self: Pin<&mut Self>) -> Option<Self::Item> {
         poll_fn(|cx| self.as_mut().poll_next(cx)).await
     }
+
+    /// Yields only the items for which `predicate` returns true.
+    fn filter<P>(self, predicate: P) -> Filter<Self, P>
+    where
+        Self: Sized,
+        P: FnMut(&Self::Item) -> bool,
+    {
+        Filter::new(self, predicate)
+    }
+
+    /// Yields the `Some` values that `f` returns, skipping the items it maps
+    /// to `None`.
+    fn filter_map<B, F>(self, f: F) -> FilterMap<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> Option<B>,
+    {
+        FilterMap::new(self, f)
+    }
+
+    /// Yields at most the first `n` items.
+    fn take(self, n: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take::new(self, n)
+    }
+
+    /// Drops the first `n` items and yields the rest.
+    fn skip(self, n: usize) -> Skip<Self>
+    where
+        Self: Sized,
+    {
+        Skip::new(self, n)
+    }
+
+    /// Yields items until `predicate` first returns false.
+    fn take_while<P>(self, predicate: P) -> TakeWhile<Self, P>
+    where
+        Self: Sized,
+        P: FnMut(&Self::Item) -> bool,
+    {
+        TakeWhile::new(self, predicate)
+    }
+
+    /// Drops items until `predicate` first returns false, then yields the
+    /// rest.
+    fn skip_while<P>(self, predicate: P) -> SkipWhile<Self, P>
+    where
+        Self: Sized,
+        P: FnMut(&Self::Item) -> bool,
+    {
+        SkipWhile::new(self, predicate)
+    }
+
+    /// Yields the first item and then every `step`th one after it.
+    ///
+    /// Panics if `step` is 0.
+    fn step_by(self, step: usize) -> StepBy<Self>
+    where
+        Self: Sized,
+    {
+        StepBy::new(self, step)
+    }
+
+    /// Pairs each item with its index.
+    fn enumerate(self) -> Enumerate<Self>
+    where
+        Self: Sized,
+    {
+        Enumerate::new(self)
+    }
+
+    /// Calls `f` on each item on its way past.
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item),
+    {
+        Inspect::new(self, f)
+    }
+
+    /// Yields the values `predicate` returns until it first returns `None`.
+    fn map_while<B, P>(self, predicate: P) -> MapWhile<Self, P>
+    where
+        Self: Sized,
+        P: FnMut(Self::Item) -> Option<B>,
+    {
+        MapWhile::new(self, predicate)
+    }
+
+    /// Collects every item into `C`.
+    fn collect<C>(self) -> Collect<Self, C>
+    where
+        Self: Sized,
+        C: Default + Extend<Self::Item>,
+    {
+        Collect::new(self)
+    }
+
+    /// Combines every item into an accumulator, starting from `init`.
+    fn fold<B, F>(self, init: B, f: F) -> Fold<Self, B, F>
+    where
+        Self: Sized,
+        F: FnMut(B, Self::Item) -> B,
+    {
+        Fold::new(self, init, f)
+    }
+
+    /// Like [`fold`](Self::fold), but stops at the first `Err` that `f`
+    /// returns.
+    fn try_fold<B, E, F>(self, init: B, f: F) -> TryFold<Self, B, F>
+    where
+        Self: Sized,
+        F: FnMut(B, Self::Item) -> Result<B, E>,
+    {
+        TryFold::new(self, init, f)
+    }
+
+    /// Resolves to the number of items left in the iterator.
+    fn count(self) -> Count<Self>
+    where
+        Self: Sized,
+    {
+        Count::new(self)
+    }
+
+    /// Resolves to the final item, or `None` if there were none.
+    fn last(self) -> Last<Self>
+    where
+        Self: Sized,
+    {
+        Last::new(self)
+    }
+
+    /// Resolves to the item at index `n`, counting from 0.
+    fn nth(self, n: usize) -> Nth<Self>
+    where
+        Self: Sized,
+    {
+        Nth::new(self, n)
+    }
+
+    /// Whether `f` returns true for some item. Stops at the first one.
+    fn any<F>(self, f: F) -> Any<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> bool,
+    {
+        Any::new(self, f)
+    }
+
+    /// Whether `f` returns true for every item. Stops at the first that
+    /// fails.
+    fn all<F>(self, f: F) -> All<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> bool,
+    {
+        All::new(self, f)
+    }
+
+    /// Resolves to the first item for which `predicate` returns true.
+    fn find<P>(self, predicate: P) -> Find<Self, P>
+    where
+        Self: Sized,
+        P: FnMut(&Self::Item) -> bool,
+    {
+        Find::new(self, predicate)
+    }
+
+    /// Resolves to the first `Some` that `f` returns.
+    fn find_map<B, F>(self, f: F) -> FindMap<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> Option<B>,
+    {
+        FindMap::new(self, f)
+    }
+
+    /// Resolves to the index of the first item for which `predicate` returns
+    /// true.
+    fn position<P>(self, predicate: P) -> Position<Self, P>
+    where
+        Self: Sized,
+        P: FnMut(Self::Item) -> bool,
+    {
+        Position::new(self, predicate)
+    }
+
+    /// Resolves to the item with the smallest key, the first one if there's a
+    /// tie.
+    fn min_by_key<K, F>(self, f: F) -> MinByKey<Self, F, K>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> K,
+        K: Ord,
+    {
+        MinByKey::new(self, f)
+    }
+
+    /// Resolves to the item with the largest key, the last one if there's a
+    /// tie.
+    fn max_by_key<K, F>(self, f: F) -> MaxByKey<Self, F, K>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> K,
+        K: Ord,
+    {
+        MaxByKey::new(self, f)
+    }
+
+    /// Calls `f` on every item.
+    fn for_each<F>(self, f: F) -> ForEach<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item),
+    {
+        ForEach::new(self, f)
+    }
+
+    /// Calls `f` on every item, stopping at the first `Err` it returns.
+    fn try_for_each<E, F>(self, f: F) -> TryForEach<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> Result<(), E>,
+    {
+        TryForEach::new(self, f)
+    }
+
+    /// Splits an iterator of pairs in two. See [`unzip`].
+    fn unzip<A, B>(self) -> (UnzipLeft<Self, A, B>, UnzipRight<Self, A, B>)
+    where
+        Self: Sized + AsyncIterator<Item = (A, B)>,
+    {
+        unzip(self)
+    }
 }
 
 impl<T: AsyncIterator> AsyncIteratorExt for T {}
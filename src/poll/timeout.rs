@@ -0,0 +1,208 @@
+//! Time limits for async iterators in the `poll_next` formulation.
+
+use std::{
+    async_iter::AsyncIterator,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use super::merge::Fused;
+use crate::{
+    future_combinators::Elapsed,
+    time::{Clock, Sleep},
+};
+
+/// Limits how long each item can take.
+///
+/// If `iter` doesn't produce an item within `duration` of it being asked for,
+/// this yields `Err(Elapsed)` and then carries on waiting for the same item
+/// with a fresh time limit. Once `iter` ends, so does this.
+pub fn timeout_each<I: AsyncIterator>(
+    clock: &Clock,
+    duration: Duration,
+    iter: I,
+) -> TimeoutEach<I> {
+    TimeoutEach {
+        iter: Fused::new(iter),
+        clock: clock.clone(),
+        duration,
+        sleep: None,
+    }
+}
+
+pub struct TimeoutEach<I: AsyncIterator> {
+    /// Fused so that nothing, not even a timeout, comes after the end.
+    iter: Fused<I>,
+    clock: Clock,
+    duration: Duration,
+    /// The timer for the item we're waiting on, started on its first poll.
+    sleep: Option<Sleep>,
+}
+
+impl<I: AsyncIterator> AsyncIterator for TimeoutEach<I> {
+    type Item = Result<I::Item, Elapsed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Poll::Ready(item) = unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+            this.sleep = None;
+            return Poll::Ready(item.map(Ok));
+        }
+        let sleep = this
+            .sleep
+            .get_or_insert_with(|| this.clock.sleep(this.duration));
+        match Pin::new(sleep).poll(cx) {
+            Poll::Ready(()) => {
+                this.sleep = None;
+                Poll::Ready(Some(Err(Elapsed)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Any number of timeouts can come between items.
+        (self.iter.size_hint().0, None)
+    }
+}
+
+/// Limits how long the whole iterator can take.
+///
+/// Once `duration` has passed, this yields `Err(Elapsed)` and then ends,
+/// dropping `iter`'s remaining items.
+pub fn timeout_total<I: AsyncIterator>(
+    clock: &Clock,
+    duration: Duration,
+    iter: I,
+) -> TimeoutTotal<I> {
+    TimeoutTotal {
+        iter,
+        sleep: clock.sleep(duration),
+        done: false,
+    }
+}
+
+pub struct TimeoutTotal<I> {
+    iter: I,
+    sleep: Sleep,
+    /// Set once we've yielded `None` or the timeout.
+    done: bool,
+}
+
+impl<I: AsyncIterator> AsyncIterator for TimeoutTotal<I> {
+    type Item = Result<I::Item, Elapsed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(item) = unsafe { Pin::new_unchecked(&mut this.iter) }.poll_next(cx) {
+            this.done = item.is_none();
+            return Poll::Ready(item.map(Ok));
+        }
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                this.done = true;
+                Poll::Ready(Some(Err(Elapsed)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, self.iter.size_hint().1.map(|n| n + 1))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{async_iter::AsyncIterator, time::Duration};
+
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use super::{timeout_each, timeout_total};
+    use crate::{future_combinators::Elapsed, poll::AsyncIteratorExt, time::Clock};
+
+    /// Yields 1, 2 and 3, sleeping for that many seconds before each.
+    fn sleepy(clock: &Clock) -> impl AsyncIterator<Item = u64> + '_ {
+        async gen move {
+            for secs in 1..=3 {
+                clock.sleep(Duration::from_secs(secs)).await;
+                yield secs;
+            }
+        }
+    }
+
+    #[test]
+    fn each_item() {
+        let clock = Clock::new();
+        let items = clock.block_on(async {
+            let mut items = vec![];
+            for await item in timeout_each(&clock, Duration::from_millis(2500), sleepy(&clock)) {
+                items.push(item);
+            }
+            items
+        });
+        assert_eq!(items, vec![Ok(1), Ok(2), Err(Elapsed), Ok(3)]);
+    }
+
+    /// Ends straight away, but returns `Pending` forever if polled again.
+    struct EndsThenStalls {
+        ended: bool,
+    }
+
+    impl AsyncIterator for EndsThenStalls {
+        type Item = ();
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<()>> {
+            if self.ended {
+                Poll::Pending
+            } else {
+                self.ended = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_after_the_end() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let iter = timeout_each(
+            &clock,
+            Duration::from_secs(1),
+            EndsThenStalls { ended: false },
+        );
+        clock.block_on(async {
+            let mut iter = std::pin::pin!(iter);
+            assert_eq!(iter.as_mut().next().await, None);
+            assert_eq!(iter.as_mut().next().await, None);
+        });
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn whole_stream() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let items = clock.block_on(async {
+            let mut items = vec![];
+            for await item in timeout_total(&clock, Duration::from_secs(4), sleepy(&clock)) {
+                items.push(item);
+            }
+            items
+        });
+        assert_eq!(items, vec![Ok(1), Ok(2), Err(Elapsed)]);
+        assert_eq!(clock.now() - start, Duration::from_secs(4));
+    }
+}
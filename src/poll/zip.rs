@@ -0,0 +1,480 @@
+//! Pairing up `poll_next` async iterators, and splitting pairs apart again.
+//!
+//! [`zip`] and [`zip_longest`] poll both sides every time, so neither one
+//! waits for the other to be asked. An item that shows up before its partner
+//! is held until the partner arrives.
+//!
+//! [`unzip`] goes the other way. The two halves share the source and a buffer
+//! for each side, so whichever half is polled pulls the next pair and leaves
+//! the other value for its sibling.
+
+use std::{
+    async_iter::AsyncIterator,
+    cell::RefCell,
+    collections::VecDeque,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use super::merge::Fused;
+use crate::EitherOrBoth;
+
+/// Polls `iter` into `slot` unless it already holds an item.
+fn fill<I: AsyncIterator>(
+    iter: Pin<&mut Fused<I>>,
+    slot: &mut Option<I::Item>,
+    cx: &mut Context<'_>,
+) {
+    if slot.is_none()
+        && let Poll::Ready(Some(item)) = iter.poll_next(cx)
+    {
+        *slot = Some(item);
+    }
+}
+
+/// Adds an item held back in a buffer to a `size_hint`.
+fn plus_buffered((lower, upper): (usize, Option<usize>), buffered: bool) -> (usize, Option<usize>) {
+    let n = usize::from(buffered);
+    (
+        lower.saturating_add(n),
+        upper.and_then(|upper| upper.checked_add(n)),
+    )
+}
+
+/// Yields pairs of items from `a` and `b`, ending as soon as either one ends.
+pub fn zip<A, B>(a: A, b: B) -> Zip<A, B>
+where
+    A: AsyncIterator,
+    B: AsyncIterator,
+{
+    Zip {
+        a: Fused::new(a),
+        b: Fused::new(b),
+        a_item: None,
+        b_item: None,
+    }
+}
+
+pub struct Zip<A: AsyncIterator, B: AsyncIterator> {
+    a: Fused<A>,
+    b: Fused<B>,
+    /// An item from `a` that is waiting for its partner from `b`.
+    a_item: Option<A::Item>,
+    b_item: Option<B::Item>,
+}
+
+impl<A, B> AsyncIterator for Zip<A, B>
+where
+    A: AsyncIterator,
+    B: AsyncIterator,
+{
+    type Item = (A::Item, B::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        // A side is only polled while its slot is empty, so a finished side
+        // never has an item waiting.
+        if this.a.is_done() || this.b.is_done() {
+            return Poll::Ready(None);
+        }
+        fill(
+            unsafe { Pin::new_unchecked(&mut this.a) },
+            &mut this.a_item,
+            cx,
+        );
+        fill(
+            unsafe { Pin::new_unchecked(&mut this.b) },
+            &mut this.b_item,
+            cx,
+        );
+
+        if this.a_item.is_some() && this.b_item.is_some() {
+            return Poll::Ready(this.a_item.take().zip(this.b_item.take()));
+        }
+        if this.a.is_done() || this.b.is_done() {
+            // The other side's item has no partner coming.
+            this.a_item = None;
+            this.b_item = None;
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a_lower, a_upper) = plus_buffered(self.a.size_hint(), self.a_item.is_some());
+        let (b_lower, b_upper) = plus_buffered(self.b.size_hint(), self.b_item.is_some());
+        let upper = match (a_upper, b_upper) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (upper, None) | (None, upper) => upper,
+        };
+        (a_lower.min(b_lower), upper)
+    }
+}
+
+/// Like [`zip`], but carries on until both sides have ended, yielding the
+/// rest of the longer one on its own.
+pub fn zip_longest<A, B>(a: A, b: B) -> ZipLongest<A, B>
+where
+    A: AsyncIterator,
+    B: AsyncIterator,
+{
+    ZipLongest {
+        a: Fused::new(a),
+        b: Fused::new(b),
+        a_item: None,
+        b_item: None,
+    }
+}
+
+pub struct ZipLongest<A: AsyncIterator, B: AsyncIterator> {
+    a: Fused<A>,
+    b: Fused<B>,
+    a_item: Option<A::Item>,
+    b_item: Option<B::Item>,
+}
+
+impl<A, B> AsyncIterator for ZipLongest<A, B>
+where
+    A: AsyncIterator,
+    B: AsyncIterator,
+{
+    type Item = EitherOrBoth<A::Item, B::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        fill(
+            unsafe { Pin::new_unchecked(&mut this.a) },
+            &mut this.a_item,
+            cx,
+        );
+        fill(
+            unsafe { Pin::new_unchecked(&mut this.b) },
+            &mut this.b_item,
+            cx,
+        );
+
+        let a_ready = this.a_item.is_some() || this.a.is_done();
+        let b_ready = this.b_item.is_some() || this.b.is_done();
+        if !(a_ready && b_ready) {
+            return Poll::Pending;
+        }
+        Poll::Ready(match (this.a_item.take(), this.b_item.take()) {
+            (Some(a), Some(b)) => Some(EitherOrBoth::Both(a, b)),
+            (Some(a), None) => Some(EitherOrBoth::Left(a)),
+            (None, Some(b)) => Some(EitherOrBoth::Right(b)),
+            (None, None) => None,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a_lower, a_upper) = plus_buffered(self.a.size_hint(), self.a_item.is_some());
+        let (b_lower, b_upper) = plus_buffered(self.b.size_hint(), self.b_item.is_some());
+        let upper = match (a_upper, b_upper) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+        (a_lower.max(b_lower), upper)
+    }
+}
+
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+
+/// Wakes both halves of an [`unzip`] when the source is ready, since either
+/// of them may be the one to pull from it next.
+#[derive(Default)]
+struct BothWakers {
+    wakers: Mutex<[Option<Waker>; 2]>,
+}
+
+impl BothWakers {
+    fn register(&self, side: usize, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        match &mut wakers[side] {
+            Some(old) if old.will_wake(waker) => {}
+            slot => *slot = Some(waker.clone()),
+        }
+    }
+
+    fn wake_side(&self, side: usize) {
+        let waker = self.wakers.lock().unwrap()[side].take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl Wake for BothWakers {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_side(LEFT);
+        self.wake_side(RIGHT);
+    }
+}
+
+/// The state shared by the two halves of an [`unzip`].
+struct Shared<I: AsyncIterator, A, B> {
+    iter: Pin<Box<Fused<I>>>,
+    left: VecDeque<A>,
+    right: VecDeque<B>,
+    /// Set when a half is dropped, so nothing more is buffered for it.
+    dropped: [bool; 2],
+    wakers: Arc<BothWakers>,
+}
+
+impl<I, A, B> Shared<I, A, B>
+where
+    I: AsyncIterator<Item = (A, B)>,
+{
+    /// Pulls the next pair from the source on behalf of `side`.
+    fn poll_pair(&mut self, side: usize, cx: &mut Context<'_>) -> Poll<Option<(A, B)>> {
+        self.wakers.register(side, cx.waker());
+        let waker = Waker::from(self.wakers.clone());
+        let poll = self
+            .iter
+            .as_mut()
+            .poll_next(&mut Context::from_waker(&waker));
+        if let Poll::Ready(None) = poll {
+            // Let the other half see the end too.
+            self.wakers.wake_side(1 - side);
+        }
+        poll
+    }
+
+    fn size_hint(&self, buffered: usize) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (
+            lower.saturating_add(buffered),
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+}
+
+/// Splits an iterator of pairs into an iterator of the first halves and one
+/// of the second halves.
+///
+/// Values for a half that falls behind are buffered until it catches up, with
+/// no limit. Once a half is dropped, its values are thrown away instead.
+pub fn unzip<I, A, B>(iter: I) -> (UnzipLeft<I, A, B>, UnzipRight<I, A, B>)
+where
+    I: AsyncIterator<Item = (A, B)>,
+{
+    let shared = Rc::new(RefCell::new(Shared {
+        iter: Box::pin(Fused::new(iter)),
+        left: VecDeque::new(),
+        right: VecDeque::new(),
+        dropped: [false; 2],
+        wakers: Arc::default(),
+    }));
+    (
+        UnzipLeft {
+            shared: shared.clone(),
+        },
+        UnzipRight { shared },
+    )
+}
+
+pub struct UnzipLeft<I: AsyncIterator, A, B> {
+    shared: Rc<RefCell<Shared<I, A, B>>>,
+}
+
+impl<I, A, B> AsyncIterator for UnzipLeft<I, A, B>
+where
+    I: AsyncIterator<Item = (A, B)>,
+{
+    type Item = A;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<A>> {
+        let mut shared = self.shared.borrow_mut();
+        if let Some(a) = shared.left.pop_front() {
+            return Poll::Ready(Some(a));
+        }
+        shared.poll_pair(LEFT, cx).map(|pair| {
+            pair.map(|(a, b)| {
+                if !shared.dropped[RIGHT] {
+                    shared.right.push_back(b);
+                    shared.wakers.wake_side(RIGHT);
+                }
+                a
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let shared = self.shared.borrow();
+        shared.size_hint(shared.left.len())
+    }
+}
+
+impl<I: AsyncIterator, A, B> Drop for UnzipLeft<I, A, B> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.dropped[LEFT] = true;
+        shared.left.clear();
+    }
+}
+
+pub struct UnzipRight<I: AsyncIterator, A, B> {
+    shared: Rc<RefCell<Shared<I, A, B>>>,
+}
+
+impl<I, A, B> AsyncIterator for UnzipRight<I, A, B>
+where
+    I: AsyncIterator<Item = (A, B)>,
+{
+    type Item = B;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<B>> {
+        let mut shared = self.shared.borrow_mut();
+        if let Some(b) = shared.right.pop_front() {
+            return Poll::Ready(Some(b));
+        }
+        shared.poll_pair(RIGHT, cx).map(|pair| {
+            pair.map(|(a, b)| {
+                if !shared.dropped[LEFT] {
+                    shared.left.push_back(a);
+                    shared.wakers.wake_side(LEFT);
+                }
+                b
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let shared = self.shared.borrow();
+        shared.size_hint(shared.right.len())
+    }
+}
+
+impl<I: AsyncIterator, A, B> Drop for UnzipRight<I, A, B> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.dropped[RIGHT] = true;
+        shared.right.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{async_iter::AsyncIterator, future::ready, pin::pin, time::Duration};
+
+    use super::{unzip, zip, zip_longest};
+    use crate::{
+        block_on,
+        future_combinators::{join_all_unordered, JoinTuple},
+        poll::{map::map, AsyncIteratorExt},
+        time::Clock,
+        EitherOrBoth::{Both, Left, Right},
+    };
+
+    /// Yields `0..n`, sleeping `secs` before each item.
+    fn ticks(clock: &Clock, secs: u64, n: u32) -> impl AsyncIterator<Item = u32> + '_ {
+        async gen move {
+            for i in 0..n {
+                clock.sleep(Duration::from_secs(secs)).await;
+                yield i;
+            }
+        }
+    }
+
+    #[test]
+    fn zip_polls_both_sides_at_once() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let pairs =
+            clock.block_on(zip(ticks(&clock, 1, 5), ticks(&clock, 2, 3)).collect::<Vec<_>>());
+        assert_eq!(pairs, vec![(0, 0), (1, 1), (2, 2)]);
+        // The slow side sets the pace; the fast one waits in the buffer.
+        assert_eq!(clock.now() - start, Duration::from_secs(6));
+    }
+
+    #[test]
+    fn zip_ends_with_shorter_side() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let pairs =
+            clock.block_on(zip(ticks(&clock, 3, 1), ticks(&clock, 1, 10)).collect::<Vec<_>>());
+        assert_eq!(pairs, vec![(0, 0)]);
+        // Stops as soon as the short side ends, rather than waiting out the
+        // long one.
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn zip_size_hint() {
+        let exact = |n| join_all_unordered((0..n).map(ready));
+        let mut zipped = pin!(zip(exact(3), exact(5)));
+        assert_eq!(zipped.size_hint(), (3, Some(3)));
+        block_on(async {
+            zipped.as_mut().next().await;
+            assert_eq!(zipped.size_hint(), (2, Some(2)));
+            while zipped.as_mut().next().await.is_some() {}
+        });
+        assert_eq!(zipped.size_hint(), (0, Some(0)));
+
+        let longest = zip_longest(exact(3), exact(5));
+        assert_eq!(longest.size_hint(), (5, Some(5)));
+    }
+
+    #[test]
+    fn zip_longest_yields_the_tail() {
+        let clock = Clock::new();
+        let items = clock
+            .block_on(zip_longest(ticks(&clock, 2, 2), ticks(&clock, 1, 4)).collect::<Vec<_>>());
+        assert_eq!(items, vec![Both(0, 0), Both(1, 1), Right(2), Right(3)]);
+
+        let items = clock.block_on(
+            zip_longest(ticks(&clock, 1, 2), async gen {
+                yield 'a';
+            })
+            .collect::<Vec<_>>(),
+        );
+        assert_eq!(items, vec![Both(0, 'a'), Left(1)]);
+    }
+
+    fn pairs() -> impl AsyncIterator<Item = (u32, char)> {
+        async gen {
+            for (i, c) in "abc".chars().enumerate() {
+                yield (i as u32, c);
+            }
+        }
+    }
+
+    #[test]
+    fn unzip_one_side_then_the_other() {
+        let (numbers, letters) = unzip(pairs());
+        block_on(async {
+            assert_eq!(numbers.collect::<Vec<_>>().await, vec![0, 1, 2]);
+            assert_eq!(letters.collect::<String>().await, "abc");
+        });
+    }
+
+    #[test]
+    fn unzip_sides_wake_each_other() {
+        let clock = Clock::new();
+        let source = map(ticks(&clock, 1, 4), |i| ready((i, i * 10)));
+        let (small, large) = unzip(source);
+        let (small, large) =
+            clock.block_on((small.collect::<Vec<_>>(), large.collect::<Vec<_>>()).join());
+        assert_eq!(small, vec![0, 1, 2, 3]);
+        assert_eq!(large, vec![0, 10, 20, 30]);
+    }
+
+    #[test]
+    fn unzip_dropped_side_is_not_buffered() {
+        let (numbers, letters) = unzip(pairs());
+        drop(letters);
+        let mut numbers = pin!(numbers);
+        assert_eq!(numbers.size_hint(), (0, None));
+        block_on(async {
+            assert_eq!(numbers.as_mut().next().await, Some(0));
+            assert!(numbers.shared.borrow().right.is_empty());
+            assert_eq!(numbers.collect::<Vec<_>>().await, vec![1, 2]);
+        });
+    }
+}
@@ -25,15 +25,15 @@ where
     async fn exec(mut self, mut f: impl async FnMut(Option<Self::Item>) -> ControlFlow<()>) {
         self.stream
             .exec(async |item| {
-                if let Some(item) = item {
-                    if (self.predicate)(&item).await {
-                        f(Some(item)).await?;
-                    }
+                if let Some(item) = item
+                    && (self.predicate)(&item).await
+                {
+                    f(Some(item)).await?;
                 }
                 ControlFlow::Continue(())
             })
             .await;
-        f(None).await;
+        let _ = f(None).await;
     }
 }
 
@@ -1,13 +1,24 @@
 //! Merge with push-based streams
+//!
+//! If a producer panics, the consumer keeps getting items from the other one,
+//! and the panic is resumed once the merge is finished. If both producers
+//! panic, only the panic from the first stream is resumed and the other one
+//! is dropped.
+//!
+//! When the consumer stops, the merge cancels its [`CancellationToken`]. From
+//! then on the producers' callbacks return `Break` instead of blocking on a
+//! pipe nobody reads, so they can wind down on their own during the grace
+//! period set with [`Merge::shutdown_with`].
 
 use std::{
     cell::RefCell,
-    future::poll_fn,
+    future::{poll_fn, ready, Ready},
+    ops::ControlFlow,
     task::{Poll, Waker},
 };
 
 use crate::{
-    future_combinators::{join, race, JoinFuture},
+    future_combinators::{join, race, CancellationToken, JoinFuture},
     Either,
 };
 
@@ -50,13 +61,26 @@ impl<T> SendPipe<'_, T> {
                 return Poll::Pending;
             }
             this.item = item.take();
-            this.waker.take().map(|w| w.wake());
+            if let Some(waker) = this.waker.take() {
+                waker.wake();
+            }
             Poll::Ready(())
         })
         .await;
     }
 }
 
+/// Hands `item` to the consumer, unless the merge is shutting down.
+async fn send<T>(tx: &mut SendPipe<'_, T>, token: &CancellationToken, item: T) -> ControlFlow<()> {
+    if token.is_cancelled() {
+        return ControlFlow::Break(());
+    }
+    match race(tx.put(item), token.cancelled()).await {
+        Either::Left(()) => ControlFlow::Continue(()),
+        Either::Right(()) => ControlFlow::Break(()),
+    }
+}
+
 struct ReceivePipe<'a, T> {
     pipe: &'a RefCell<OnePipeInner<T>>,
 }
@@ -71,16 +95,20 @@ impl<T> ReceivePipe<'_, T> {
                 return Poll::Pending;
             }
             let item = this.item.take().unwrap();
-            this.waker.take().map(|w| w.wake());
+            if let Some(waker) = this.waker.take() {
+                waker.wake();
+            }
             Poll::Ready(item)
         })
         .await
     }
 }
 
-pub struct Merge<A, B> {
+pub struct Merge<A, B, G = Ready<()>> {
     a: A,
     b: B,
+    token: CancellationToken,
+    grace: G,
 }
 
 impl<A, B> Merge<A, B>
@@ -88,21 +116,50 @@ where
     A: Stream,
     B: Stream,
 {
+    /// Merges `a` and `b`. Without [`Merge::shutdown_with`], producers that
+    /// are still running when the consumer stops are dropped right away.
     pub fn new(a: A, b: B) -> Self {
-        Merge { a, b }
+        Merge {
+            a,
+            b,
+            token: CancellationToken::new(),
+            grace: ready(()),
+        }
     }
 }
 
-impl<A, B> Stream for Merge<A, B>
+impl<A, B, G> Merge<A, B, G> {
+    /// Cancels `token` when the consumer stops and gives the producers until
+    /// `grace` completes to finish before they are dropped.
+    ///
+    /// `grace` isn't polled until the consumer stops.
+    pub fn shutdown_with<H>(self, token: CancellationToken, grace: H) -> Merge<A, B, H>
+    where
+        H: IntoFuture<Output = ()>,
+    {
+        Merge {
+            a: self.a,
+            b: self.b,
+            token,
+            grace,
+        }
+    }
+}
+
+impl<A, B, G> Stream for Merge<A, B, G>
 where
     A: Stream,
     B: Stream,
+    G: IntoFuture<Output = ()>,
 {
     type Item = Either<A::Item, B::Item>;
 
     async fn exec(self, mut f: impl async FnMut(Option<Self::Item>) -> std::ops::ControlFlow<()>) {
+        let Merge { a, b, token, grace } = self;
+        let mut panic = None;
         with_pipe(async |mut atx, mut arx| {
             with_pipe(async |mut btx, mut brx| {
+                let (mut a_panic, mut b_panic) = (None, None);
                 join(async {
                     let mut a_done = false;
                     let mut b_done = false;
@@ -148,35 +205,170 @@ where
                     }
                 })
                 .with(async {
-                    self.a
-                        .exec(async |item| {
-                            atx.put(item).await;
-                            std::ops::ControlFlow::Continue(())
-                        })
-                        .await;
+                    let producer = a.exec(async |item| send(&mut atx, &token, item).await);
+                    // A producer that panics has nothing more to send.
+                    if let Err(caught) = join(producer).catch_unwind().await {
+                        a_panic = Some(caught);
+                        let _ = send(&mut atx, &token, None).await;
+                    }
                 })
                 .with(async {
-                    self.b
-                        .exec(async |item| {
-                            btx.put(item).await;
-                            std::ops::ControlFlow::Continue(())
-                        })
-                        .await;
+                    let producer = b.exec(async |item| send(&mut btx, &token, item).await);
+                    if let Err(caught) = join(producer).catch_unwind().await {
+                        b_panic = Some(caught);
+                        let _ = send(&mut btx, &token, None).await;
+                    }
                 })
-                .await
+                .shutdown_with(token.clone(), grace)
+                .await;
+                // Only one panic can be resumed. The first producer's wins, and
+                // the other payload is dropped here.
+                panic = match (a_panic, b_panic) {
+                    (Some(a), b) => {
+                        drop(b);
+                        Some(a)
+                    }
+                    (None, b) => b,
+                };
             })
             .await
         })
-        .await
+        .await;
+        if let Some(panic) = panic {
+            panic.resume();
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::push::{from_iter, Stream};
+    use std::cell::{Cell, RefCell};
+    use std::ops::ControlFlow;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::Duration;
+
+    use crate::executor::{random::sweep, LocalExecutor, RandomExecutor};
+    use crate::future_combinators::{race, CancellationToken};
+    use crate::push::{from_async_iter, from_iter, Stream};
+    use crate::time::Clock;
     use crate::{block_on, Either};
 
-    use super::Merge;
+    use super::{Merge, OnePipeInner, ReceivePipe, SendPipe};
+
+    #[test]
+    fn producer_panic_does_not_stop_consumer() {
+        let a = from_async_iter(async gen {
+            yield 1;
+            yield 2;
+            panic!("producer");
+        });
+        let b = from_iter(4..7);
+        let mut output = Vec::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            block_on(Merge::new(a, b).for_each(async |item| {
+                output.push(item.into_inner());
+            }))
+        }));
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<&str>(),
+            Some(&"producer")
+        );
+        output.sort();
+        assert_eq!(output, vec![1, 2, 4, 5, 6]);
+    }
+
+    #[test]
+    fn both_producers_panic() {
+        let a = from_async_iter(async gen {
+            yield 1;
+            panic!("first");
+        });
+        let b = from_async_iter(async gen {
+            yield 2;
+            panic!("second");
+        });
+        let mut output = Vec::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            block_on(Merge::new(a, b).for_each(async |item| {
+                output.push(item.into_inner());
+            }))
+        }));
+        assert_eq!(result.unwrap_err().downcast_ref::<&str>(), Some(&"first"));
+        output.sort();
+        assert_eq!(output, vec![1, 2]);
+    }
+
+    /// Counts up forever, and takes a second to flush once it is told to stop.
+    struct Flushing<'a> {
+        clock: &'a Clock,
+        flushed: &'a Cell<bool>,
+    }
+
+    impl Stream for Flushing<'_> {
+        type Item = u32;
+
+        async fn exec(self, mut f: impl async FnMut(Option<u32>) -> ControlFlow<()>) {
+            for i in 0.. {
+                if f(Some(i)).await.is_break() {
+                    break;
+                }
+            }
+            self.clock.sleep(Duration::from_secs(1)).await;
+            self.flushed.set(true);
+        }
+    }
+
+    #[test]
+    fn consumer_break_lets_producers_shut_down() {
+        let clock = Clock::new();
+        let token = CancellationToken::new();
+        let (a_flushed, b_flushed) = (Cell::new(false), Cell::new(false));
+        let a = Flushing {
+            clock: &clock,
+            flushed: &a_flushed,
+        };
+        let b = Flushing {
+            clock: &clock,
+            flushed: &b_flushed,
+        };
+        let merged = Merge::new(a, b).shutdown_with(token.clone(), async {
+            clock.sleep(Duration::from_secs(5)).await;
+        });
+        let mut count = 0;
+        clock.block_on(merged.exec(async |_| {
+            count += 1;
+            if count == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }));
+        assert!(token.is_cancelled());
+        assert!(a_flushed.get());
+        assert!(b_flushed.get());
+    }
+
+    #[test]
+    fn consumer_break_without_grace_drops_producers() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let flushed = Cell::new(false);
+        let a = Flushing {
+            clock: &clock,
+            flushed: &flushed,
+        };
+        let mut count = 0;
+        clock.block_on(Merge::new(a, from_iter(0..)).exec(async |_| {
+            count += 1;
+            if count == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }));
+        assert!(!flushed.get());
+        assert_eq!(clock.now(), start);
+    }
 
     #[test]
     fn test_merge() {
@@ -194,4 +386,81 @@ mod test {
             assert!(output.contains(&i));
         }
     }
+
+    #[test]
+    fn merge_keeps_track_of_sides() {
+        let merged = Merge::new(from_iter(["a", "b"].into_iter()), from_iter(1..3));
+        let mut lefts = Vec::new();
+        let mut rights = Vec::new();
+        block_on(merged.for_each(async |item| {
+            item.either(|s| lefts.push(s), |n| rights.push(n));
+        }));
+        assert_eq!(lefts, vec!["a", "b"]);
+        assert_eq!(rights, vec![1, 2]);
+    }
+
+    /// Same shape as `Merge::exec`, but each producer runs as its own task
+    /// instead of as a `JoinWith` branch.
+    #[test]
+    fn merge_with_spawned_producers() {
+        let a_pipe = RefCell::new(OnePipeInner::new());
+        let b_pipe = RefCell::new(OnePipeInner::new());
+        let mut output = Vec::new();
+        let ex = LocalExecutor::new();
+
+        let mut atx = SendPipe { pipe: &a_pipe };
+        ex.spawn_local(from_iter(1..=3).exec(async move |item| {
+            atx.put(item).await;
+            ControlFlow::Continue(())
+        }));
+        let mut btx = SendPipe { pipe: &b_pipe };
+        ex.spawn_local(from_iter(4..=6).exec(async move |item| {
+            btx.put(item).await;
+            ControlFlow::Continue(())
+        }));
+
+        ex.run_until(async {
+            let mut arx = ReceivePipe { pipe: &a_pipe };
+            let mut brx = ReceivePipe { pipe: &b_pipe };
+            let mut a_done = false;
+            let mut b_done = false;
+            while !(a_done && b_done) {
+                match race(arx.get(), brx.get()).await {
+                    Either::Left(Some(i)) | Either::Right(Some(i)) => output.push(i),
+                    Either::Left(None) => a_done = true,
+                    Either::Right(None) => b_done = true,
+                }
+            }
+        });
+
+        output.sort();
+        assert_eq!(output, vec![1, 2, 3, 4, 5, 6]);
+        assert!(ex.is_empty());
+    }
+
+    #[test]
+    fn merge_random_schedules() {
+        sweep(1000, |seed| {
+            let ex = RandomExecutor::new(seed);
+            let a = from_async_iter(ex.interrupt(async gen {
+                yield 1;
+                yield 2;
+                yield 3;
+            }));
+            let b = from_async_iter(ex.interrupt(async gen {
+                yield 4;
+                yield 5;
+                yield 6;
+            }));
+
+            let (mut left, mut right) = (vec![], vec![]);
+            ex.run_until(Merge::new(a, b).for_each(async |item| match item {
+                Either::Left(i) => left.push(i),
+                Either::Right(i) => right.push(i),
+            }));
+
+            assert_eq!(left, vec![1, 2, 3]);
+            assert_eq!(right, vec![4, 5, 6]);
+        });
+    }
 }
@@ -4,8 +4,9 @@ use std::{async_iter::AsyncIterator, ops::ControlFlow, pin::pin};
 
 use crate::poll::AsyncIteratorExt as _;
 
-mod filter;
-mod merge;
+pub mod filter;
+pub mod merge;
+pub mod timeout;
 
 pub trait Stream {
     type Item;
@@ -17,9 +18,8 @@ pub trait Stream {
         Self: Sized,
     {
         self.exec(async |item| {
-            match item {
-                Some(item) => f(item).await,
-                None => {}
+            if let Some(item) = item {
+                f(item).await;
             }
             ControlFlow::Continue(())
         })
@@ -27,7 +27,7 @@ pub trait Stream {
     }
 }
 
-fn from_async_iter<I: AsyncIterator>(iter: I) -> impl Stream<Item = I::Item> {
+pub fn from_async_iter<I: AsyncIterator>(iter: I) -> impl Stream<Item = I::Item> {
     struct Iter<I: AsyncIterator>(I);
 
     impl<I: AsyncIterator> Stream for Iter<I> {
@@ -62,13 +62,12 @@ where
         type Item = I::Item;
 
         async fn exec(self, mut f: impl async FnMut(Option<Self::Item>) -> ControlFlow<()>) {
-            let mut iter = self.0;
-            while let Some(item) = iter.next() {
+            for item in self.0 {
                 if let ControlFlow::Break(()) = f(Some(item)).await {
                     return;
                 }
             }
-            f(None).await;
+            let _ = f(None).await;
         }
     }
 
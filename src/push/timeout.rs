@@ -0,0 +1,211 @@
+//! Time limits for push-based iterators.
+//!
+//! A push stream can't be paused and picked up again, so when a limit runs out
+//! we drop the producer, pass `Err(Elapsed)` to the consumer, and end the
+//! stream.
+
+use std::{cell::Cell, future::poll_fn, ops::ControlFlow, pin::Pin, task::Poll, time::Duration};
+
+use super::Stream;
+use crate::{
+    future_combinators::{deadline, race_with, Elapsed, Fairness},
+    time::{Clock, Instant, Sleep},
+};
+
+/// Limits how long the producer can take to push each item.
+///
+/// The time the consumer spends handling an item doesn't count. If the next
+/// item doesn't arrive within `duration` after that, the stream ends with
+/// `Err(Elapsed)`.
+pub fn timeout_each<S: Stream>(clock: &Clock, duration: Duration, stream: S) -> TimeoutEach<S> {
+    TimeoutEach {
+        stream,
+        clock: clock.clone(),
+        duration,
+    }
+}
+
+pub struct TimeoutEach<S> {
+    stream: S,
+    clock: Clock,
+    duration: Duration,
+}
+
+impl<S: Stream> Stream for TimeoutEach<S> {
+    type Item = Result<S::Item, Elapsed>;
+
+    async fn exec(self, mut f: impl async FnMut(Option<Self::Item>) -> ControlFlow<()>) {
+        let clock = &self.clock;
+        let duration = self.duration;
+        // The deadline for the next item, or `None` while the consumer is
+        // handling one.
+        let next_deadline = Cell::new(Some(clock.now() + duration));
+        let producer = self.stream.exec(async |item| {
+            next_deadline.set(None);
+            let flow = f(item.map(Ok)).await;
+            next_deadline.set(Some(clock.now() + duration));
+            flow
+        });
+        let mut sleep: Option<Sleep> = None;
+        let watchdog = poll_fn(|cx| match next_deadline.get() {
+            Some(deadline) => {
+                let sleep = match &mut sleep {
+                    Some(sleep) if sleep.deadline() == deadline => sleep,
+                    slot => slot.insert(clock.sleep_until(deadline)),
+                };
+                Pin::new(sleep).poll(cx)
+            }
+            // Drop the timer so the clock can't jump to its stale deadline
+            // while the consumer is busy. The producer is polled first on
+            // every wakeup, so we'll see the new deadline as soon as the
+            // consumer is done.
+            None => {
+                sleep = None;
+                Poll::Pending
+            }
+        });
+        if race_with(producer, watchdog, Fairness::Biased)
+            .await
+            .is_right()
+            && f(Some(Err(Elapsed))).await.is_continue()
+        {
+            let _ = f(None).await;
+        }
+    }
+}
+
+/// Limits how long the whole stream can take, including the time the consumer
+/// spends on each item.
+///
+/// The consumer runs inside the producer's callback, so if the deadline passes
+/// while it is handling an item, that call is cancelled along with the
+/// producer: it is dropped at whatever `await` it is stuck on, and the item is
+/// never fully handled. The consumer is then called again with
+/// `Err(Elapsed)`.
+pub fn timeout_total<S: Stream>(clock: &Clock, duration: Duration, stream: S) -> TimeoutTotal<S> {
+    TimeoutTotal {
+        stream,
+        clock: clock.clone(),
+        deadline: clock.now() + duration,
+    }
+}
+
+pub struct TimeoutTotal<S> {
+    stream: S,
+    clock: Clock,
+    deadline: Instant,
+}
+
+impl<S: Stream> Stream for TimeoutTotal<S> {
+    type Item = Result<S::Item, Elapsed>;
+
+    async fn exec(self, mut f: impl async FnMut(Option<Self::Item>) -> ControlFlow<()>) {
+        // Dropping the producer on timeout also drops any `f(item)` call it
+        // is in the middle of.
+        let producer = self.stream.exec(async |item| f(item.map(Ok)).await);
+        if deadline(&self.clock, self.deadline, producer)
+            .await
+            .is_err()
+            && f(Some(Err(Elapsed))).await.is_continue()
+        {
+            let _ = f(None).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{async_iter::AsyncIterator, future::poll_fn, task::Poll, thread, time::Duration};
+
+    use super::{timeout_each, timeout_total};
+    use crate::{
+        future_combinators::Elapsed,
+        push::{from_async_iter, Stream},
+        time::Clock,
+    };
+
+    /// Yields 1, 2 and 3, sleeping for that many seconds before each.
+    fn sleepy(clock: &Clock) -> impl AsyncIterator<Item = u64> + '_ {
+        async gen move {
+            for secs in 1..=3 {
+                clock.sleep(Duration::from_secs(secs)).await;
+                yield secs;
+            }
+        }
+    }
+
+    #[test]
+    fn each_item() {
+        let clock = Clock::new();
+        let mut items = vec![];
+        let stream = from_async_iter(sleepy(&clock));
+        clock.block_on(
+            timeout_each(&clock, Duration::from_millis(2500), stream)
+                .for_each(async |item| items.push(item)),
+        );
+        assert_eq!(items, vec![Ok(1), Ok(2), Err(Elapsed)]);
+    }
+
+    #[test]
+    fn slow_consumer_does_not_count() {
+        let clock = Clock::new();
+        let mut items = vec![];
+        let stream = from_async_iter(sleepy(&clock));
+        clock.block_on(
+            timeout_each(&clock, Duration::from_millis(3500), stream).for_each(async |item| {
+                clock.sleep(Duration::from_secs(10)).await;
+                items.push(item);
+            }),
+        );
+        assert_eq!(items, vec![Ok(1), Ok(2), Ok(3)]);
+    }
+
+    #[test]
+    fn clock_stands_still_while_consumer_is_busy() {
+        let clock = Clock::new();
+        let mut items = vec![];
+        let mut threads = vec![];
+        let stream = from_async_iter(sleepy(&clock));
+        clock.block_on(
+            timeout_each(&clock, Duration::from_millis(3500), stream).for_each(async |item| {
+                // Wait on something other than the clock. With no timers
+                // left, the clock has nowhere to jump to.
+                let before = clock.now();
+                let mut spawned = None;
+                poll_fn(|cx| match spawned {
+                    None => {
+                        let waker = cx.waker().clone();
+                        spawned = Some(thread::spawn(move || {
+                            thread::sleep(Duration::from_millis(10));
+                            waker.wake();
+                        }));
+                        Poll::Pending
+                    }
+                    Some(_) => Poll::Ready(()),
+                })
+                .await;
+                threads.extend(spawned);
+                assert_eq!(clock.now(), before);
+                items.push(item);
+            }),
+        );
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(items, vec![Ok(1), Ok(2), Ok(3)]);
+    }
+
+    #[test]
+    fn whole_stream() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let mut items = vec![];
+        let stream = from_async_iter(sleepy(&clock));
+        clock.block_on(
+            timeout_total(&clock, Duration::from_secs(4), stream)
+                .for_each(async |item| items.push(item)),
+        );
+        assert_eq!(items, vec![Ok(1), Ok(2), Err(Elapsed)]);
+        assert_eq!(clock.now() - start, Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,37 @@
+//! A seeded random number generator shared by the parts of the crate that
+//! need one, like [`RandomExecutor`](crate::executor::random::RandomExecutor)
+//! and [`Fairness::Random`](crate::future_combinators::Fairness::Random).
+
+use std::cell::Cell;
+
+/// A small splitmix64 generator, so we don't need to pull in `rand`.
+pub struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            state: Cell::new(seed),
+        }
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    pub fn below(&self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Returns true one time in `odds`.
+    pub fn one_in(&self, odds: u64) -> bool {
+        self.next_u64().is_multiple_of(odds)
+    }
+}
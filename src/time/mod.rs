@@ -0,0 +1,199 @@
+//! A virtual clock for testing time-based combinators.
+//!
+//! Time only moves when someone calls [`Clock::advance`], or when an executor
+//! that knows about the clock runs out of ready tasks and jumps straight to the
+//! next timer deadline. That keeps tests deterministic and means they never
+//! actually sleep.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    ops::{Add, AddAssign, Sub},
+    rc::Rc,
+    task::Waker,
+    time::Duration,
+};
+
+mod sleep;
+
+pub use sleep::{Interval, Sleep};
+
+/// A point in virtual time, measured from when the [`Clock`] was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(Duration);
+
+impl Instant {
+    /// Returns the amount of time elapsed from `earlier` to `self`, or zero if
+    /// `earlier` is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0 + rhs)
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// A manually driven clock.
+///
+/// Cloning a `Clock` gives another handle to the same clock.
+#[derive(Clone, Default)]
+pub struct Clock {
+    inner: Rc<RefCell<ClockInner>>,
+}
+
+#[derive(Default)]
+struct ClockInner {
+    now: Instant,
+    /// Registered timers, keyed by deadline and then by a unique id so that
+    /// several timers can share a deadline.
+    timers: BTreeMap<(Instant, u64), Waker>,
+    next_id: u64,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn now(&self) -> Instant {
+        self.inner.borrow().now
+    }
+
+    /// Returns a future that completes once the clock reaches `now() + duration`.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        self.sleep_until(self.now() + duration)
+    }
+
+    /// Returns a future that completes once the clock reaches `deadline`.
+    pub fn sleep_until(&self, deadline: Instant) -> Sleep {
+        Sleep::new(self.clone(), deadline)
+    }
+
+    /// Returns an async iterator that yields the current time once every
+    /// `period`, starting one period from now.
+    pub fn interval(&self, period: Duration) -> Interval {
+        Interval::new(self.clone(), period)
+    }
+
+    /// Moves the clock forward by `duration`, waking any timers that expire.
+    pub fn advance(&self, duration: Duration) {
+        let now = self.now() + duration;
+        self.advance_to(now);
+    }
+
+    /// Moves the clock forward to `instant`, waking any timers that expire.
+    ///
+    /// Does nothing if `instant` is in the past.
+    pub fn advance_to(&self, instant: Instant) {
+        let expired = {
+            let mut inner = self.inner.borrow_mut();
+            if instant <= inner.now {
+                return;
+            }
+            inner.now = instant;
+            let pending = inner.timers.split_off(&(instant, u64::MAX));
+            std::mem::replace(&mut inner.timers, pending)
+        };
+        // Wake outside the borrow in case a waker polls a timer directly.
+        for waker in expired.into_values() {
+            waker.wake();
+        }
+    }
+
+    /// Returns the earliest deadline of any pending timer.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.inner
+            .borrow()
+            .timers
+            .first_key_value()
+            .map(|(&(deadline, _), _)| deadline)
+    }
+
+    /// Jumps to the next timer deadline, if there is one.
+    ///
+    /// Executors call this when they have no ready tasks. Returns `false` if
+    /// there were no timers to fire.
+    pub fn advance_to_next_deadline(&self) -> bool {
+        match self.next_deadline() {
+            Some(deadline) => {
+                self.advance_to(deadline);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Like [`crate::block_on`], but jumps to the next timer deadline instead
+    /// of parking when the future is waiting.
+    pub fn block_on<F: IntoFuture>(&self, f: F) -> F::Output {
+        crate::block_on_with_clock(f, Some(self))
+    }
+
+    fn register(&self, deadline: Instant, id: Option<u64>, waker: &Waker) -> u64 {
+        let mut inner = self.inner.borrow_mut();
+        let id = id.unwrap_or_else(|| {
+            inner.next_id += 1;
+            inner.next_id
+        });
+        match inner.timers.get_mut(&(deadline, id)) {
+            Some(old) => old.clone_from(waker),
+            None => {
+                inner.timers.insert((deadline, id), waker.clone());
+            }
+        }
+        id
+    }
+
+    fn deregister(&self, deadline: Instant, id: u64) {
+        self.inner.borrow_mut().timers.remove(&(deadline, id));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Clock, Instant};
+
+    #[test]
+    fn advance() {
+        let clock = Clock::new();
+        assert_eq!(clock.now(), Instant::default());
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(clock.now() - Instant::default(), Duration::from_secs(3));
+        clock.advance_to(Instant::default());
+        assert_eq!(clock.now() - Instant::default(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn jump_to_next_deadline() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let result = clock.block_on(async {
+            clock.sleep(Duration::from_secs(60)).await;
+            clock.sleep(Duration::from_secs(30)).await;
+            clock.now()
+        });
+        assert_eq!(result - start, Duration::from_secs(90));
+        assert_eq!(clock.next_deadline(), None);
+    }
+}
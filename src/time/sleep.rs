@@ -0,0 +1,214 @@
+//! Timer futures and async iterators driven by a [`Clock`].
+
+use std::{
+    async_iter::AsyncIterator,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use super::{Clock, Instant};
+
+/// Completes once its clock reaches the deadline.
+///
+/// Returned by [`Clock::sleep`] and [`Clock::sleep_until`].
+pub struct Sleep {
+    clock: Clock,
+    deadline: Instant,
+    /// Set while the timer is registered with the clock.
+    id: Option<u64>,
+}
+
+impl Sleep {
+    pub(super) fn new(clock: Clock, deadline: Instant) -> Self {
+        Sleep {
+            clock,
+            deadline,
+            id: None,
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        self.clock.now() >= self.deadline
+    }
+
+    /// Moves the deadline. The new deadline takes effect the next time this is
+    /// polled.
+    pub fn reset(&mut self, deadline: Instant) {
+        if let Some(id) = self.id.take() {
+            self.clock.deregister(self.deadline, id);
+        }
+        self.deadline = deadline;
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.is_elapsed() {
+            if let Some(id) = this.id.take() {
+                this.clock.deregister(this.deadline, id);
+            }
+            Poll::Ready(())
+        } else {
+            this.id = Some(this.clock.register(this.deadline, this.id, cx.waker()));
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.clock.deregister(self.deadline, id);
+        }
+    }
+}
+
+/// Yields the deadline of each tick, once every `period`.
+///
+/// Returned by [`Clock::interval`]. If the clock jumps past several ticks at
+/// once, each missed tick is yielded immediately rather than skipped.
+pub struct Interval {
+    sleep: Sleep,
+    period: Duration,
+}
+
+impl Interval {
+    pub(super) fn new(clock: Clock, period: Duration) -> Self {
+        assert!(!period.is_zero(), "interval period must be non-zero");
+        let first = clock.now() + period;
+        Interval {
+            sleep: Sleep::new(clock, first),
+            period,
+        }
+    }
+}
+
+impl AsyncIterator for Interval {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Instant>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                let tick = this.sleep.deadline();
+                this.sleep.reset(tick + this.period);
+                Poll::Ready(Some(tick))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{async_iter::AsyncIterator, future::poll_fn, pin::pin, task::Poll, time::Duration};
+
+    use crate::{
+        executor::{random::sweep, LocalExecutor, RandomExecutor},
+        future_combinators::{join, race, JoinFuture},
+        poll::AsyncIteratorExt,
+        time::Clock,
+        Either::Right,
+    };
+
+    #[test]
+    fn race_against_sleep() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let result = clock.block_on(race(
+            clock.sleep(Duration::from_secs(5)),
+            clock.sleep(Duration::from_secs(2)),
+        ));
+        assert!(matches!(result, Right(())));
+        assert_eq!(clock.now() - start, Duration::from_secs(2));
+        // The losing timer was dropped, so it no longer holds the clock back.
+        assert_eq!(clock.next_deadline(), None);
+    }
+
+    #[test]
+    fn join_sleeps() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let elapsed = clock.block_on(
+            join(async {
+                clock.sleep(Duration::from_secs(3)).await;
+                clock.now() - start
+            })
+            .with(clock.sleep(Duration::from_secs(1))),
+        );
+        assert_eq!(elapsed, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn interval_ticks() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let ticks = clock.block_on(async {
+            let mut interval = pin!(clock.interval(Duration::from_millis(10)));
+            let mut ticks = vec![];
+            for _ in 0..3 {
+                ticks.push(interval.as_mut().next().await.unwrap() - start);
+            }
+            ticks
+        });
+        assert_eq!(ticks, [10, 20, 30].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn interval_catches_up() {
+        let clock = Clock::new();
+        let mut interval = pin!(clock.interval(Duration::from_secs(1)));
+        clock.advance(Duration::from_secs(3));
+        let mut ticks = 0;
+        clock.block_on(poll_fn(|cx| {
+            while interval.as_mut().poll_next(cx).is_ready() {
+                ticks += 1;
+            }
+            Poll::Ready(())
+        }));
+        assert_eq!(ticks, 3);
+    }
+
+    #[test]
+    fn local_executor_jumps_to_deadline() {
+        let clock = Clock::new();
+        let ex = LocalExecutor::new().with_clock(clock.clone());
+        let a = ex.spawn_local(async {
+            clock.sleep(Duration::from_secs(10)).await;
+            clock.now()
+        });
+        let b = ex.spawn_local(async {
+            clock.sleep(Duration::from_secs(20)).await;
+            clock.now()
+        });
+        let (a, b) = ex.run_until(async { (a.await, b.await) });
+        assert_eq!(b - a, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn random_executor_jumps_to_deadline() {
+        sweep(200, |seed| {
+            let clock = Clock::new();
+            let start = clock.now();
+            let ex = RandomExecutor::new(seed).with_clock(clock.clone());
+            let fast = ex.spawn_local(clock.sleep(Duration::from_secs(1)));
+            let slow = ex.spawn_local(clock.sleep(Duration::from_secs(2)));
+            let result = ex.run_until(race(ex.interrupt(slow), ex.interrupt(fast)));
+            assert!(matches!(result, Right(())));
+            assert_eq!(clock.now() - start, Duration::from_secs(1));
+        });
+    }
+}